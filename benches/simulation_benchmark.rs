@@ -1,43 +1,269 @@
+use std::time::Duration;
+
 use criterion::*;
-use falling_rust::element::Element;
-use falling_rust::sandbox::SandBox;
+use falling_rust::pseudo_random::PseudoRandom;
+use falling_rust::sandbox::{DirtyRect, Element, SandBox};
 use falling_rust::simulation::{simulation_step, Simulation};
 
-// Note: to get a meaningful benchmark avoid simulations that become static afer a number of iterations
-fn criterion_benchmark(criterion: &mut Criterion) {
-    let size = 64;
-    let mut simulation = Simulation::new();
-
-    // Empty sandbox (should be fast)
-    let mut sandbox = SandBox::new(size, size);
-    criterion.bench_function("empty_simulation", |b| {
-        b.iter(|| simulation_step(&mut simulation, &mut sandbox))
-    });
-
-    // Water flowing from top to bottom (pretty much slowest element)
-    let mut sandbox = SandBox::new(size, size);
-    for x in 0..size / 4 {
-        sandbox.set_element(x * 4, 1, Element::WaterSource, 0);
+// Sandbox sizes each scene is measured at. The largest no longer fits in the caches, so it shows
+// the effect of the cell layout.
+const SIZES: [usize; 4] = [64, 256, 512, 1024];
+
+// A scene to benchmark: builds a sandbox of the given size, which is simulated for a number of
+// warm-up steps before measuring, so the measured step is representative of the scene in motion
+struct Scene {
+    name: &'static str,
+    warm_up_steps: usize,
+    build: fn(usize) -> SandBox,
+}
+
+const SCENES: [Scene; 11] = [
+    // Nothing to do, which mostly measures the overhead of sleeping chunks
+    Scene {
+        name: "empty",
+        warm_up_steps: 0,
+        build: |size| SandBox::new(size, size),
+    },
+    // Solids do not move, so their chunks are updated for a while and then fall asleep
+    Scene {
+        name: "solid_rock",
+        warm_up_steps: 0,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            fill(&mut sandbox, 0.0..1.0, 0.5..1.0, Element::Rock);
+            sandbox
+        },
+    },
+    Scene {
+        name: "powder_sand",
+        warm_up_steps: 4,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            fill(&mut sandbox, 0.0..1.0, 0.0..0.5, Element::Sand);
+            sandbox
+        },
+    },
+    Scene {
+        name: "liquid_water",
+        warm_up_steps: 4,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            fill(&mut sandbox, 0.0..1.0, 0.0..0.5, Element::Water);
+            sandbox
+        },
+    },
+    Scene {
+        name: "gas_smoke",
+        warm_up_steps: 4,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            fill(&mut sandbox, 0.0..1.0, 0.5..1.0, Element::Smoke);
+            sandbox
+        },
+    },
+    // Water flowing from sources at the top to drains at the bottom, which never settles
+    Scene {
+        name: "water_flow",
+        warm_up_steps: 0,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            for x in (1..size - 1).step_by(4) {
+                sandbox.set_element(x, 1, Element::WaterSource);
+            }
+            for x in (1..size - 1).step_by(3) {
+                sandbox.set_element(x, size - 2, Element::Drain);
+            }
+            sandbox
+        },
+    },
+    Scene {
+        name: "burning_oil",
+        warm_up_steps: 20,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            fill(&mut sandbox, 0.0..1.0, 0.5..1.0, Element::Oil);
+            for x in (1..size - 1).step_by(8) {
+                sandbox.set_element(x, size - 2, Element::FireSource);
+            }
+            sandbox
+        },
+    },
+    // A block of TNT set off in the middle, measured while the explosion spreads
+    Scene {
+        name: "explosion",
+        warm_up_steps: 3,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            fill(&mut sandbox, 0.25..0.75, 0.25..0.75, Element::TNT);
+            sandbox.set_element_with_strength(size / 2, size / 2, Element::Explosion, 200);
+            sandbox
+        },
+    },
+    // Seeds on wet soil growing into plants
+    Scene {
+        name: "plant_growth",
+        warm_up_steps: 20,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            fill(&mut sandbox, 0.0..1.0, 0.8..1.0, Element::Sand);
+            fill(&mut sandbox, 0.0..1.0, 0.75..0.8, Element::Water);
+            for x in (1..size - 1).step_by(4) {
+                sandbox.set_element(x, size * 3 / 4 - 1, Element::Seed);
+            }
+            sandbox
+        },
+    },
+    // Random soup of Game of Life cells
+    Scene {
+        name: "life",
+        warm_up_steps: 0,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            let mut random = PseudoRandom::new();
+            for y in 1..size - 1 {
+                for x in 1..size - 1 {
+                    if random.next().is_multiple_of(3) {
+                        sandbox.set_element(x, y, Element::Life);
+                    }
+                }
+            }
+            sandbox
+        },
+    },
+    // A bit of everything
+    Scene {
+        name: "mixed",
+        warm_up_steps: 10,
+        build: |size| {
+            let mut sandbox = SandBox::new(size, size);
+            fill(&mut sandbox, 0.0..0.3, 0.0..0.4, Element::Sand);
+            fill(&mut sandbox, 0.35..0.65, 0.0..0.4, Element::Water);
+            fill(&mut sandbox, 0.7..1.0, 0.0..0.4, Element::Oil);
+            fill(&mut sandbox, 0.0..1.0, 0.6..0.65, Element::Wood);
+            fill(&mut sandbox, 0.1..0.2, 0.8..1.0, Element::Lava);
+            fill(&mut sandbox, 0.4..0.6, 0.8..1.0, Element::Acid);
+            fill(&mut sandbox, 0.7..0.9, 0.8..1.0, Element::Iron);
+            sandbox
+        },
+    },
+];
+
+// Fill a part of the sandbox, given as fractions of its size
+fn fill(
+    sandbox: &mut SandBox,
+    xs: std::ops::Range<f32>,
+    ys: std::ops::Range<f32>,
+    element: Element,
+) {
+    let (width, height) = (sandbox.width() as f32, sandbox.height() as f32);
+    for y in (ys.start * height) as usize..(ys.end * height) as usize {
+        for x in (xs.start * width) as usize..(xs.end * width) as usize {
+            sandbox.set_element(x, y, element);
+        }
+    }
+}
+
+// Measure single simulation steps starting from the warmed up state of each scene
+fn simulation_benchmark(criterion: &mut Criterion) {
+    for scene in &SCENES {
+        let mut group = criterion.benchmark_group(scene.name);
+        configure(&mut group);
+        for size in SIZES {
+            let mut sandbox = (scene.build)(size);
+            let mut simulation = Simulation::new();
+            for _ in 0..scene.warm_up_steps {
+                simulation_step(&mut simulation, &mut sandbox);
+            }
+            group.throughput(Throughput::Elements((size * size) as u64));
+            group.bench_function(BenchmarkId::from_parameter(size), |b| {
+                b.iter_batched_ref(
+                    || (simulation.clone(), sandbox.clone()),
+                    |(simulation, sandbox)| simulation_step(simulation, sandbox),
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+        group.finish();
     }
-    for x in 0..size / 3 {
-        sandbox.set_element(x * 3, size - 1, Element::Drain, 0);
+}
+
+// Measure converting all cells to pixels, as done when a new sandbox is drawn
+fn render_benchmark(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("render");
+    configure(&mut group);
+    let mixed = SCENES.iter().find(|scene| scene.name == "mixed").unwrap();
+    for size in SIZES {
+        let sandbox = (mixed.build)(size);
+        let mut pixels = vec![0; size * size * 4];
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| sandbox.render_pixels(black_box(&mut pixels)))
+        });
     }
-    criterion.bench_function("water_flow_simulation", |b| {
-        b.iter(|| simulation_step(&mut simulation, &mut sandbox))
-    });
-
-    // Oil and fire
-    let mut sandbox = SandBox::new(size, size);
-    for x in 0..size / 4 {
-        sandbox.set_element(x * 4, 1, Element::OilSource, 0);
+    group.finish();
+}
+
+// Measure adding the glow of glowing cells to the pixels of the whole sandbox
+fn render_glow_benchmark(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("render_glow");
+    configure(&mut group);
+    let mixed = SCENES.iter().find(|scene| scene.name == "mixed").unwrap();
+    for size in SIZES {
+        let sandbox = (mixed.build)(size);
+        let mut pixels = vec![0; size * size * 4];
+        let area = DirtyRect {
+            min_x: 0,
+            min_y: 0,
+            max_x: size - 1,
+            max_y: size - 1,
+        };
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| sandbox.render_glow(area, black_box(&mut pixels)))
+        });
     }
-    for x in 0..size / 3 {
-        sandbox.set_element(x * 3, size - 1, Element::FireSource, 0);
+    group.finish();
+}
+
+// Measure converting only the cells changed by a step to pixels, as done by the render system
+// each frame
+fn render_changes_benchmark(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("render_changes");
+    configure(&mut group);
+    let mixed = SCENES.iter().find(|scene| scene.name == "mixed").unwrap();
+    for size in SIZES {
+        let mut sandbox = (mixed.build)(size);
+        let mut simulation = Simulation::new();
+        for _ in 0..mixed.warm_up_steps {
+            simulation_step(&mut simulation, &mut sandbox);
+        }
+        sandbox.clear_dirty_rects();
+        simulation_step(&mut simulation, &mut sandbox);
+        let mut pixels = vec![0; size * size * 4];
+        group.throughput(Throughput::Elements((size * size) as u64));
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                for area in sandbox.dirty_rects() {
+                    sandbox.render_area(area, None, black_box(&mut pixels));
+                }
+            })
+        });
     }
-    criterion.bench_function("burning_oil_simulation", |b| {
-        b.iter(|| simulation_step(&mut simulation, &mut sandbox))
-    });
+    group.finish();
+}
+
+fn configure(group: &mut BenchmarkGroup<measurement::WallTime>) {
+    group
+        .sample_size(30)
+        .warm_up_time(Duration::from_millis(500))
+        .measurement_time(Duration::from_secs(2));
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(
+    benches,
+    simulation_benchmark,
+    render_benchmark,
+    render_glow_benchmark,
+    render_changes_benchmark
+);
 criterion_main!(benches);
@@ -0,0 +1,188 @@
+// Headless runner: loads a sandbox, simulates it without opening a window and writes the result.
+// Used for batch runs such as regression tests and generating thumbnails.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Instant;
+
+use falling_rust::sandbox::*;
+use falling_rust::simulation::{simulation_step, Simulation, DEFAULT_SEED};
+
+const USAGE: &str = "\
+Usage: falling-rust-cli <input> [options]
+
+Loads a saved sandbox, or a PNG image when the input ends in .png, and simulates it.
+
+Options:
+  --steps <n>          Number of steps to simulate (default 0)
+  --seed <n>           Simulation seed (default 12345)
+  --elements <file>    Element definitions to use (default elements.ron, if it exists)
+  --save <file>        Write the result as a save file
+  --png <file>         Write the result as a PNG image
+  --scale <n>          Pixels per cell in the PNG image (default 1)
+  --timings <file>     Write the duration of each step as CSV
+  --help               Show this message";
+
+struct Options {
+    input: String,
+    steps: u64,
+    seed: u64,
+    elements: Option<String>,
+    save: Option<String>,
+    png: Option<String>,
+    scale: u32,
+    timings: Option<String>,
+}
+
+fn main() -> ExitCode {
+    let arguments: Vec<String> = std::env::args().skip(1).collect();
+    if arguments.iter().any(|argument| argument == "--help") {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let options = match parse_options(&arguments) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    match run(&options) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_options(arguments: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        input: String::new(),
+        steps: 0,
+        seed: DEFAULT_SEED,
+        elements: None,
+        save: None,
+        png: None,
+        scale: 1,
+        timings: None,
+    };
+    let mut input = None;
+    let mut arguments = arguments.iter();
+    while let Some(argument) = arguments.next() {
+        if !argument.starts_with("--") {
+            if input.replace(argument.clone()).is_some() {
+                return Err(format!("Unexpected argument {}", argument));
+            }
+            continue;
+        }
+        let value = arguments
+            .next()
+            .ok_or_else(|| format!("Missing value for {}", argument))?;
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("Invalid number for {}: {}", argument, value))
+        };
+        match argument.as_str() {
+            "--steps" => options.steps = number()?,
+            "--seed" => options.seed = number()?,
+            "--elements" => options.elements = Some(value.clone()),
+            "--save" => options.save = Some(value.clone()),
+            "--png" => options.png = Some(value.clone()),
+            "--scale" => {
+                options.scale = number()?
+                    .try_into()
+                    .ok()
+                    .filter(|scale| (1..=64).contains(scale))
+                    .ok_or_else(|| format!("Scale must be between 1 and 64: {}", value))?
+            }
+            "--timings" => options.timings = Some(value.clone()),
+            _ => return Err(format!("Unknown option {}", argument)),
+        }
+    }
+    options.input = input.ok_or("Missing input file")?;
+    Ok(options)
+}
+
+fn run(options: &Options) -> Result<(), String> {
+    // Elements must be installed before the sandbox is loaded, as it refers to them by id
+    match &options.elements {
+        Some(path) => install_element_file(path)
+            .map_err(|error| format!("Cannot load elements from {}: {}", path, error))?,
+        None if Path::new(ELEMENT_FILE_NAME).exists() => install_element_file(ELEMENT_FILE_NAME)
+            .map_err(|error| {
+                format!("Cannot load elements from {}: {}", ELEMENT_FILE_NAME, error)
+            })?,
+        None => {}
+    }
+
+    let mut sandbox = load(&options.input)
+        .map_err(|error| format!("Cannot load {}: {}", options.input, error))?;
+    let mut simulation = Simulation::with_seed(options.seed);
+    let mut timings = match &options.timings {
+        Some(path) => {
+            let mut writer = BufWriter::new(
+                File::create(path).map_err(|error| format!("Cannot create {}: {}", path, error))?,
+            );
+            writeln!(writer, "step,microseconds,awake_chunks")
+                .map_err(|error| format!("Cannot write {}: {}", path, error))?;
+            Some(writer)
+        }
+        None => None,
+    };
+
+    let start = Instant::now();
+    for step in 0..options.steps {
+        let step_start = Instant::now();
+        simulation_step(&mut simulation, &mut sandbox);
+        let duration = step_start.elapsed();
+        for error in simulation.script_errors.drain(..) {
+            eprintln!("{}", error);
+        }
+        if let Some(writer) = &mut timings {
+            writeln!(
+                writer,
+                "{},{},{}",
+                step,
+                duration.as_micros(),
+                sandbox.awake_chunk_count()
+            )
+            .map_err(|error| format!("Cannot write timings: {}", error))?;
+        }
+    }
+    if let Some(mut writer) = timings {
+        writer
+            .flush()
+            .map_err(|error| format!("Cannot write timings: {}", error))?;
+    }
+    eprintln!(
+        "Simulated {} steps of a {}x{} sandbox in {:.2} s",
+        options.steps,
+        sandbox.width(),
+        sandbox.height(),
+        start.elapsed().as_secs_f64()
+    );
+
+    if let Some(path) = &options.save {
+        sandbox
+            .save_file(path)
+            .map_err(|error| format!("Cannot save {}: {}", path, error))?;
+    }
+    if let Some(path) = &options.png {
+        sandbox
+            .export_png_file(path, options.scale)
+            .map_err(|error| format!("Cannot export {}: {}", path, error))?;
+    }
+    Ok(())
+}
+
+fn load(path: &str) -> Result<SandBox, Box<dyn std::error::Error>> {
+    if path.to_lowercase().ends_with(".png") {
+        Ok(SandBox::import_png_file(path, &ElementPalette::elements())?)
+    } else {
+        Ok(SandBox::load_file(path)?)
+    }
+}
@@ -0,0 +1,204 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem::size_of_val;
+
+#[cfg(feature = "bevy")]
+use bevy::prelude::Resource;
+
+use crate::sandbox::*;
+
+pub const DEFAULT_MEMORY_BUDGET: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug)]
+struct CellChange {
+    x: u32,
+    y: u32,
+    before: Cell,
+    after: Cell,
+}
+
+// The cells edited during a single stroke (mouse down to mouse up), with their original state and
+// the state the tool left them in
+#[derive(Default)]
+pub struct Stroke {
+    changes: Vec<CellChange>,
+    touched: HashMap<(usize, usize), usize>,
+}
+
+impl Stroke {
+    // Edit a cell, remembering its state before it is edited for the first time in this stroke and
+    // the state the edit leaves it in. The cells are not read again when the stroke ends, as the
+    // simulation may have moved the painted elements away by then.
+    pub fn edit(
+        &mut self,
+        sandbox: &mut SandBox,
+        x: usize,
+        y: usize,
+        edit: impl FnOnce(&mut SandBox),
+    ) {
+        let index = *self.touched.entry((x, y)).or_insert_with(|| {
+            let state = *sandbox.get(x, y);
+            self.changes.push(CellChange {
+                x: x as u32,
+                y: y as u32,
+                before: state,
+                after: state,
+            });
+            self.changes.len() - 1
+        });
+        edit(sandbox);
+        self.changes[index].after = *sandbox.get(x, y);
+    }
+
+    // Drop the cells that did not change
+    fn finish(mut self) -> Vec<CellChange> {
+        self.changes.retain(|change| change.before != change.after);
+        self.changes.shrink_to_fit();
+        self.changes
+    }
+}
+
+// Undo and redo stacks of strokes, limited to a memory budget by dropping the oldest strokes
+#[cfg_attr(feature = "bevy", derive(Resource))]
+pub struct History {
+    undo_stack: VecDeque<Vec<CellChange>>,
+    redo_stack: Vec<Vec<CellChange>>,
+    stroke: Option<Stroke>,
+    memory_budget: usize,
+    // Bytes used by the strokes on both stacks
+    memory_usage: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::with_budget(DEFAULT_MEMORY_BUDGET)
+    }
+}
+
+impl History {
+    pub fn with_budget(memory_budget: usize) -> Self {
+        Self {
+            undo_stack: VecDeque::new(),
+            redo_stack: Vec::new(),
+            stroke: None,
+            memory_budget,
+            memory_usage: 0,
+        }
+    }
+
+    pub fn begin_stroke(&mut self) {
+        if self.stroke.is_none() {
+            self.stroke = Some(Stroke::default());
+        }
+    }
+
+    // The active stroke, to pass to ToolBox::apply_tracked
+    pub fn stroke_mut(&mut self) -> Option<&mut Stroke> {
+        self.stroke.as_mut()
+    }
+
+    // Finish the active stroke, making it undoable
+    pub fn end_stroke(&mut self) {
+        let Some(stroke) = self.stroke.take() else {
+            return;
+        };
+        let changes = stroke.finish();
+        if changes.is_empty() {
+            return;
+        }
+        self.memory_usage += stroke_size(&changes);
+        self.undo_stack.push_back(changes);
+        for changes in self.redo_stack.drain(..) {
+            self.memory_usage -= stroke_size(&changes);
+        }
+        self.enforce_budget();
+    }
+
+    // Undo the last stroke, returning the cells that were changed
+    pub fn undo(&mut self, sandbox: &mut SandBox) -> Vec<(usize, usize, Cell)> {
+        self.end_stroke();
+        let Some(changes) = self.undo_stack.pop_back() else {
+            return Vec::new();
+        };
+        let edits = restore(sandbox, &changes, |change| change.before);
+        self.redo_stack.push(changes);
+        edits
+    }
+
+    // Redo the last undone stroke, returning the cells that were changed
+    pub fn redo(&mut self, sandbox: &mut SandBox) -> Vec<(usize, usize, Cell)> {
+        self.end_stroke();
+        let Some(changes) = self.redo_stack.pop() else {
+            return Vec::new();
+        };
+        let edits = restore(sandbox, &changes, |change| change.after);
+        self.undo_stack.push_back(changes);
+        self.enforce_budget();
+        edits
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    // Forget all strokes, e.g. when the sandbox is replaced
+    pub fn clear(&mut self) {
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.stroke = None;
+        self.memory_usage = 0;
+    }
+
+    // Approximate memory used by the stored strokes in bytes
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    pub fn memory_budget(&self) -> usize {
+        self.memory_budget
+    }
+
+    // Change the memory budget, dropping the oldest strokes when they no longer fit
+    pub fn set_memory_budget(&mut self, memory_budget: usize) {
+        self.memory_budget = memory_budget;
+        self.enforce_budget();
+    }
+
+    fn enforce_budget(&mut self) {
+        while self.memory_usage > self.memory_budget {
+            // Redo steps go first, as they are the least likely to be needed
+            let dropped = if !self.redo_stack.is_empty() {
+                Some(self.redo_stack.remove(0))
+            } else {
+                self.undo_stack.pop_front()
+            };
+            match dropped {
+                Some(changes) => self.memory_usage -= stroke_size(&changes),
+                None => break,
+            }
+        }
+    }
+}
+
+// Approximate memory used by a stroke in bytes
+fn stroke_size(changes: &[CellChange]) -> usize {
+    size_of_val(changes)
+}
+
+fn restore(
+    sandbox: &mut SandBox,
+    changes: &[CellChange],
+    state: impl Fn(&CellChange) -> Cell,
+) -> Vec<(usize, usize, Cell)> {
+    changes
+        .iter()
+        .map(|change| {
+            let (x, y, state) = (change.x as usize, change.y as usize, state(change));
+            sandbox.set_cell(x, y, state);
+            (x, y, state)
+        })
+        .collect()
+}
@@ -1,4 +1,4 @@
-use bevy::prelude::*;
+use bevy::{ecs::system::SystemParam, prelude::*};
 use bevy_egui::{
     egui::{self, Color32, ColorImage, Frame, Layout, Response, style::*, TextureHandle, Ui},
     EguiContexts, EguiPlugin,
@@ -6,13 +6,14 @@ use bevy_egui::{
 use egui::{Align2, FontId, Mesh, Pos2, Rect, Shape, Vec2};
 use image::{DynamicImage, GenericImageView};
 
-use crate::{
-    sandbox::*,
-    simulation::*,
-    spawn_sandbox,
-};
-use crate::interface::toolbox::*;
-use crate::sandbox::ELEMENT_COUNT;
+use falling_rust::history::History;
+use falling_rust::replay::ReplayEvent;
+use falling_rust::sandbox::*;
+use falling_rust::simulation::*;
+use falling_rust::toolbox::*;
+
+use crate::interface::session::Session;
+use crate::render::RenderSettings;
 
 const ICON_SIZE: f32 = 64.0;
 
@@ -23,9 +24,23 @@ impl Plugin for GuiPlugin {
         app.add_plugins(EguiPlugin)
             .add_systems(Startup, setup_gui)
             .add_systems(Update, gui_system);
+
+        app.add_systems(Update, undo_shortcut_system);
+
+        #[cfg(not(target_family = "wasm"))]
+        app.add_systems(Update, export_shortcut_system);
     }
 }
 
+// Resources of the simulation, the session recording and the undo history that the panels edit
+#[derive(SystemParam)]
+pub struct SimulationResources<'w> {
+    pub toolbox: ResMut<'w, ToolBox>,
+    pub simulation: ResMut<'w, Simulation>,
+    pub session: ResMut<'w, Session>,
+    pub history: ResMut<'w, History>,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum GuiMode {
     MainGui,
@@ -33,6 +48,7 @@ pub enum GuiMode {
     ToolSelect,
     SandboxSettings,
     MoveView,
+    Inspect,
 }
 
 #[derive(Resource)]
@@ -50,10 +66,19 @@ pub struct SandboxGui {
     pub icon_zoom_in_handle: TextureHandle,
     pub icon_zoom_out_handle: TextureHandle,
     pub icon_move_handle: TextureHandle,
+    pub icon_inspect_handle: TextureHandle,
     pub icon_settings_handle: TextureHandle,
     pub icon_eraser_handle: TextureHandle,
     pub icon_step_handle: TextureHandle,
-    pub element_icons: [TextureHandle; ELEMENT_COUNT],
+    pub icon_undo_handle: TextureHandle,
+    pub icon_redo_handle: TextureHandle,
+    // Icons of all elements, indexed by element id
+    pub element_icons: Vec<TextureHandle>,
+    pub file_name: String,
+    pub file_status: String,
+    pub export_file_name: String,
+    pub export_scale: u32,
+    pub export_overlays: bool,
 }
 
 
@@ -68,35 +93,9 @@ fn setup_gui(mut commands: Commands, mut egui_contexts: EguiContexts) {
 
     // Generate element icons
     let background = image::load_from_memory(include_bytes!("../../assets/icon_element.png")).unwrap();
-    let element_icons = [
-        generate_element_image(Element::Air, &mut egui_contexts, &background),
-        generate_element_image(Element::Sand, &mut egui_contexts, &background),
-        generate_element_image(Element::Rock, &mut egui_contexts, &background),
-        generate_element_image(Element::Water, &mut egui_contexts, &background),
-        generate_element_image(Element::Acid, &mut egui_contexts, &background),
-        generate_element_image(Element::Drain, &mut egui_contexts, &background),
-        generate_element_image(Element::Wood, &mut egui_contexts, &background),
-        generate_element_image(Element::Iron, &mut egui_contexts, &background),
-        generate_element_image(Element::Rust, &mut egui_contexts, &background),
-        generate_element_image(Element::Fire, &mut egui_contexts, &background),
-        generate_element_image(Element::Ash, &mut egui_contexts, &background),
-        generate_element_image(Element::Oil, &mut egui_contexts, &background),
-        generate_element_image(Element::Lava, &mut egui_contexts, &background),
-        generate_element_image(Element::Smoke, &mut egui_contexts, &background),
-        generate_element_image(Element::Life, &mut egui_contexts, &background),
-        generate_element_image(Element::Seed, &mut egui_contexts, &background),
-        generate_element_image(Element::Plant, &mut egui_contexts, &background),
-        generate_element_image(Element::TNT, &mut egui_contexts, &background),
-        generate_element_image(Element::Gunpowder, &mut egui_contexts, &background),
-        generate_element_image(Element::Fuse, &mut egui_contexts, &background),
-        generate_element_image(Element::Explosion, &mut egui_contexts, &background),
-        generate_element_image(Element::WaterSource, &mut egui_contexts, &background),
-        generate_element_image(Element::AcidSource, &mut egui_contexts, &background),
-        generate_element_image(Element::OilSource, &mut egui_contexts, &background),
-        generate_element_image(Element::FireSource, &mut egui_contexts, &background),
-        generate_element_image(Element::LavaSource, &mut egui_contexts, &background),
-        generate_element_image(Element::Indestructible, &mut egui_contexts, &background),
-    ];
+    let element_icons = all_elements()
+        .map(|element| generate_element_image(element, &mut egui_contexts, &background))
+        .collect();
 
     commands.insert_resource(SandboxGui {
         mode: GuiMode::MainGui,
@@ -156,6 +155,11 @@ fn setup_gui(mut commands: Commands, mut egui_contexts: EguiContexts) {
             "icon_move",
             include_bytes!("../../assets/icon_move.png"),
         ),
+        icon_inspect_handle: add_icon(
+            &mut egui_contexts,
+            "icon_inspect",
+            include_bytes!("../../assets/icon_inspect.png"),
+        ),
         icon_settings_handle: add_icon(
             &mut egui_contexts,
             "icon_settings",
@@ -171,7 +175,22 @@ fn setup_gui(mut commands: Commands, mut egui_contexts: EguiContexts) {
             "icon_step",
             include_bytes!("../../assets/icon_step.png"),
         ),
+        icon_undo_handle: add_icon(
+            &mut egui_contexts,
+            "icon_undo",
+            include_bytes!("../../assets/icon_undo.png"),
+        ),
+        icon_redo_handle: add_icon(
+            &mut egui_contexts,
+            "icon_redo",
+            include_bytes!("../../assets/icon_redo.png"),
+        ),
         element_icons,
+        file_name: "sandbox.frs".to_string(),
+        file_status: String::new(),
+        export_file_name: "sandbox.png".to_string(),
+        export_scale: 1,
+        export_overlays: false,
     });
 }
 
@@ -180,34 +199,44 @@ pub fn gui_system(
     mut egui_contexts: EguiContexts,
     mut camera: Query<&mut Transform, With<Camera>>,
     mut gui: ResMut<SandboxGui>,
-    mut toolbox: ResMut<ToolBox>,
-    mut simulation: ResMut<Simulation>,
-    sandbox: Query<(Entity, &mut SandBox)>,
-    commands: Commands,
+    mut resources: SimulationResources,
+    mut sandbox: Query<(&mut SandBox, &Handle<Image>)>,
     images: ResMut<Assets<Image>>,
+    mut render_settings: ResMut<RenderSettings>,
 ) {
     right_side_toolbar(
         &mut egui_contexts,
         &mut gui,
-        &mut simulation,
+        &mut resources.simulation,
+        &mut resources.session,
         camera.single_mut().as_mut(),
     );
 
-    bottom_toolbar(&mut egui_contexts, &mut gui, &mut toolbox);
+    if let Ok((mut sandbox, _)) = sandbox.get_single_mut() {
+        bottom_toolbar(
+            &mut egui_contexts,
+            &mut gui,
+            &mut resources.toolbox,
+            &mut resources.history,
+            &mut sandbox,
+            &mut resources.session,
+            &resources.simulation,
+        );
+    }
 
     if gui.mode == GuiMode::SandboxSettings {
         settings_panel(
             &mut egui_contexts,
             sandbox,
-            commands,
             images,
-            &simulation,
+            &mut resources,
             &mut gui,
+            &mut render_settings,
         );
     } else if gui.mode == GuiMode::ElementSelect {
-        element_select_panel(&mut egui_contexts, &mut gui, &mut toolbox);
+        element_select_panel(&mut egui_contexts, &mut gui, &mut resources.toolbox);
     } else if gui.mode == GuiMode::ToolSelect {
-        side_panel_left_tool_select(egui_contexts, gui, toolbox);
+        side_panel_left_tool_select(egui_contexts, gui, resources.toolbox);
     }
 }
 
@@ -311,25 +340,11 @@ fn element_select_panel(
                 )
                     .with_main_wrap(true),
                 |ui| {
-                    element_button_click(ui, gui, Element::Sand, toolbox);
-                    element_button_click(ui, gui, Element::Wood, toolbox);
-                    element_button_click(ui, gui, Element::Iron, toolbox);
-                    element_button_click(ui, gui, Element::Rock, toolbox);
-                    element_button_click(ui, gui, Element::Water, toolbox);
-                    element_button_click(ui, gui, Element::Acid, toolbox);
-                    element_button_click(ui, gui, Element::Oil, toolbox);
-                    element_button_click(ui, gui, Element::Lava, toolbox);
-                    element_button_click(ui, gui, Element::Fire, toolbox);
-                    element_button_click(ui, gui, Element::Life, toolbox);
-                    element_button_click(ui, gui, Element::Seed, toolbox);
-                    element_button_click(ui, gui, Element::TNT, toolbox);
-                    element_button_click(ui, gui, Element::Gunpowder, toolbox);
-                    element_button_click(ui, gui, Element::Fuse, toolbox);
-                    element_button_click(ui, gui, Element::WaterSource, toolbox);
-                    element_button_click(ui, gui, Element::AcidSource, toolbox);
-                    element_button_click(ui, gui, Element::LavaSource, toolbox);
-                    element_button_click(ui, gui, Element::FireSource, toolbox);
-                    element_button_click(ui, gui, Element::Drain, toolbox);
+                    for element in all_elements() {
+                        if element_type(element).selectable {
+                            element_button_click(ui, gui, element, toolbox);
+                        }
+                    }
                 },
             );
         });
@@ -338,52 +353,179 @@ fn element_select_panel(
 // World settings panel
 fn settings_panel(
     egui_contexts: &mut EguiContexts,
-    mut sandbox: Query<(Entity, &mut SandBox)>,
-    mut commands: Commands,
+    mut sandbox: Query<(&mut SandBox, &Handle<Image>)>,
     mut images: ResMut<Assets<Image>>,
-    simulation: &Simulation,
+    resources: &mut SimulationResources,
     gui: &mut ResMut<SandboxGui>,
+    render_settings: &mut ResMut<RenderSettings>,
 ) {
+    let SimulationResources {
+        toolbox,
+        simulation,
+        session,
+        history,
+    } = resources;
     egui::SidePanel::left("settings").show(egui_contexts.ctx_mut(), |ui| {
-        let (entity, sandbox) = sandbox.single_mut();
-        let mut new_sandbox_size = None;
+        let (mut sandbox, image_handle) = sandbox.single_mut();
+        let mut new_sandbox = None;
 
         ui.label("New sandbox:");
         if ui.button("Tiny (64x64)").clicked() {
-            new_sandbox_size = Some((64, 64));
+            new_sandbox = Some(SandBox::new(64, 64));
         }
         if ui.button("Small (128x128)").clicked() {
-            new_sandbox_size = Some((128, 128));
+            new_sandbox = Some(SandBox::new(128, 128));
         }
         if ui.button("Normal (256x256)").clicked() {
-            new_sandbox_size = Some((256, 256));
+            new_sandbox = Some(SandBox::new(256, 256));
         }
         if ui.button("Large (512x512)").clicked() {
-            new_sandbox_size = Some((512, 512));
+            new_sandbox = Some(SandBox::new(512, 512));
         }
         if ui.button("Huge (1024x1024)").clicked() {
-            new_sandbox_size = Some((1024, 1024));
+            new_sandbox = Some(SandBox::new(1024, 1024));
+        }
+
+        // No file system access in the browser
+        #[cfg(not(target_family = "wasm"))]
+        {
+            ui.separator();
+            ui.label("Sandbox file:");
+            ui.text_edit_singleline(&mut gui.file_name);
+            ui.horizontal(|ui| {
+                if ui.button("Save").clicked() {
+                    gui.file_status = match sandbox.save_file(&gui.file_name) {
+                        Ok(()) => format!("Saved {}", gui.file_name),
+                        Err(error) => format!("Save failed: {}", error),
+                    };
+                }
+                if ui.button("Load").clicked() {
+                    match SandBox::load_file(&gui.file_name) {
+                        Ok(loaded) => {
+                            gui.file_status = format!("Loaded {}", gui.file_name);
+                            new_sandbox = Some(loaded);
+                        }
+                        Err(error) => gui.file_status = format!("Load failed: {}", error),
+                    }
+                }
+                if ui.button("Import PNG").clicked() {
+                    let palette = ElementPalette::elements();
+                    match SandBox::import_png_file(&gui.file_name, &palette) {
+                        Ok(imported) => {
+                            gui.file_status = format!("Imported {}", gui.file_name);
+                            new_sandbox = Some(imported);
+                        }
+                        Err(error) => gui.file_status = format!("Import failed: {}", error),
+                    }
+                }
+            });
+            ui.label("Export PNG:");
+            ui.text_edit_singleline(&mut gui.export_file_name);
+            ui.add(egui::Slider::new(&mut gui.export_scale, 1..=8).text("Scale"));
+            ui.checkbox(&mut gui.export_overlays, "Include overlays");
+            if ui.button("Export (F12)").clicked() {
+                gui.file_status = export_png(&sandbox, render_settings.overlay, gui);
+            }
+            if !gui.file_status.is_empty() {
+                ui.label(&gui.file_status);
+            }
+
+            ui.separator();
+            ui.label("Session recording:");
+            ui.text_edit_singleline(&mut session.file_name);
+            ui.horizontal(|ui| {
+                if session.recorder.is_recording() {
+                    if ui.button("Stop recording").clicked() {
+                        session.stop_recording(simulation);
+                    }
+                } else if session.is_replaying() {
+                    if ui.button("Stop replay").clicked() {
+                        session.replay = None;
+                        session.status = "Replay stopped".to_string();
+                    }
+                } else {
+                    if ui.button("Record").clicked() {
+                        session.start_recording(&mut sandbox, simulation, toolbox);
+                    }
+                    if ui.button("Replay").clicked() {
+                        session.start_replay(
+                            &mut sandbox,
+                            images.get_mut(image_handle).unwrap(),
+                            simulation,
+                        );
+                        history.clear();
+                    }
+                }
+            });
+            if !session.status.is_empty() {
+                ui.label(&session.status);
+            }
         }
 
-        if let Some((width, height)) = new_sandbox_size {
-            commands.entity(entity).despawn();
-            spawn_sandbox(
-                commands,
-                images.as_mut(),
-                width,
-                height,
+        if let Some(new_sandbox) = new_sandbox {
+            session.record(simulation, ReplayEvent::sandbox_replaced(&new_sandbox));
+            replace_sandbox(
+                &mut sandbox,
+                images.get_mut(image_handle).unwrap(),
+                new_sandbox,
             );
+            history.clear();
             gui.mode = GuiMode::MainGui;
         }
         ui.separator();
+        let mut budget_mb = history.memory_budget() / (1024 * 1024);
+        if ui
+            .add(egui::Slider::new(&mut budget_mb, 1..=256).text("Undo memory (MB)"))
+            .changed()
+        {
+            history.set_memory_budget(budget_mb * 1024 * 1024);
+        }
+        ui.label(format!(
+            "Undo history: {} KB",
+            history.memory_usage() / 1024
+        ));
+        ui.separator();
         ui.label(format!(
             "Simulation: {} ms",
             simulation.frame_time_ms
         ));
         ui.label(format!(
-            "Rendering: {} ms",
-            sandbox.render_time_ms
+            "Seed: {}, step {}",
+            simulation.seed, simulation.tick
+        ));
+        for error in &simulation.script_errors {
+            ui.label(error);
+        }
+        let (chunks_x, chunks_y) = sandbox.chunk_count();
+        let awake_chunks = sandbox.awake_chunk_count();
+        ui.label(format!(
+            "Chunks: {} active, {} sleeping",
+            awake_chunks,
+            chunks_x * chunks_y - awake_chunks
+        ));
+        ui.label(format!(
+            "Rendering: {} ms, {}% of cells redrawn",
+            sandbox.render_time_ms,
+            sandbox.rendered_cells * 100 / (sandbox.width() * sandbox.height())
         ));
+        ui.label(format!(
+            "Render time saved: {:.2} ms",
+            sandbox.render_time_saved_ms
+        ));
+        // Only set when changed, as changing the settings draws the whole sandbox again
+        let mut settings = **render_settings;
+        ui.checkbox(&mut settings.glow, "Glow");
+        let overlay_name = |overlay: Option<Overlay>| {
+            overlay.map_or("None".to_string(), |overlay| overlay.to_string())
+        };
+        egui::ComboBox::from_label("Debug overlay")
+            .selected_text(overlay_name(settings.overlay))
+            .show_ui(ui, |ui| {
+                for candidate in std::iter::once(None).chain(OVERLAYS.map(Some)) {
+                    ui.selectable_value(&mut settings.overlay, candidate, overlay_name(candidate));
+                }
+            });
+        render_settings.set_if_neq(settings);
         ui.separator();
         ui.hyperlink_to("Made by Bas@Fantastimaker", "https://fantastimaker.nl");
         ui.hyperlink_to("Using Bevy", "https://bevyengine.org");
@@ -394,6 +536,10 @@ fn bottom_toolbar(
     egui_contexts: &mut EguiContexts,
     gui: &mut ResMut<SandboxGui>,
     toolbox: &mut ResMut<ToolBox>,
+    history: &mut History,
+    sandbox: &mut SandBox,
+    session: &mut Session,
+    simulation: &Simulation,
 ) {
     egui::TopBottomPanel::bottom("bottom_panel")
         .frame(Frame::none())
@@ -448,6 +594,32 @@ fn bottom_toolbar(
                         gui.mode = GuiMode::ToolSelect;
                     }
                 };
+
+                let undo_button = egui::widgets::ImageButton::new(
+                    &gui.icon_undo_handle,
+                )
+                    .frame(false);
+                let undo_button = if history.can_undo() {
+                    undo_button
+                } else {
+                    undo_button.tint(Color32::DARK_GRAY)
+                };
+                if ui.add(undo_button).clicked() {
+                    undo(history, sandbox, session, simulation);
+                };
+
+                let redo_button = egui::widgets::ImageButton::new(
+                    &gui.icon_redo_handle,
+                )
+                    .frame(false);
+                let redo_button = if history.can_redo() {
+                    redo_button
+                } else {
+                    redo_button.tint(Color32::DARK_GRAY)
+                };
+                if ui.add(redo_button).clicked() {
+                    redo(history, sandbox, session, simulation);
+                };
             });
         });
 }
@@ -456,6 +628,7 @@ fn right_side_toolbar(
     egui_contexts: &mut EguiContexts,
     gui: &mut ResMut<SandboxGui>,
     simulation: &mut ResMut<Simulation>,
+    session: &mut Session,
     transform: &mut Transform,
 ) {
     egui::SidePanel::right("right_panel")
@@ -493,6 +666,7 @@ fn right_side_toolbar(
                 .clicked()
             {
                 simulation.running = !simulation.running;
+                session.record(simulation, ReplayEvent::SetRunning(simulation.running));
             };
             if !simulation.running {
                 if ui
@@ -505,6 +679,7 @@ fn right_side_toolbar(
                     .clicked()
                 {
                     simulation.step = true;
+                    session.record(simulation, ReplayEvent::Step);
                 };
             }
 
@@ -547,9 +722,108 @@ fn right_side_toolbar(
                     GuiMode::MoveView
                 }
             };
+            let inspect_button =
+                egui::widgets::ImageButton::new(&gui.icon_inspect_handle)
+                    .frame(false);
+            let inspect_button = if gui.mode == GuiMode::Inspect {
+                inspect_button.tint(Color32::LIGHT_GREEN)
+            } else {
+                inspect_button
+            };
+            if ui.add(inspect_button).clicked() {
+                gui.mode = if gui.mode == GuiMode::Inspect {
+                    GuiMode::MainGui
+                } else {
+                    GuiMode::Inspect
+                }
+            };
         });
 }
 
+// Export the sandbox to a PNG file, returning a status message
+#[cfg(not(target_family = "wasm"))]
+fn export_png(sandbox: &SandBox, overlay: Option<Overlay>, gui: &SandboxGui) -> String {
+    // Drawn from the cells rather than taken from the rendered texture, which includes glow
+    let image = match overlay.filter(|_| gui.export_overlays) {
+        Some(overlay) => sandbox.to_overlay_image(overlay),
+        None => sandbox.to_image(),
+    };
+    match write_png_file(&gui.export_file_name, &image, gui.export_scale) {
+        Ok(()) => format!("Exported {}", gui.export_file_name),
+        Err(error) => format!("Export failed: {}", error),
+    }
+}
+
+// Undo the last stroke, recording the restored cells when recording a session
+fn undo(
+    history: &mut History,
+    sandbox: &mut SandBox,
+    session: &mut Session,
+    simulation: &Simulation,
+) {
+    if session.is_replaying() {
+        return;
+    }
+    let cells = history.undo(sandbox);
+    if !cells.is_empty() {
+        session.record(simulation, ReplayEvent::SetCells(cells));
+    }
+}
+
+// Redo the last undone stroke, recording the restored cells when recording a session
+fn redo(
+    history: &mut History,
+    sandbox: &mut SandBox,
+    session: &mut Session,
+    simulation: &Simulation,
+) {
+    if session.is_replaying() {
+        return;
+    }
+    let cells = history.redo(sandbox);
+    if !cells.is_empty() {
+        session.record(simulation, ReplayEvent::SetCells(cells));
+    }
+}
+
+// Keyboard shortcuts for undo (Ctrl+Z) and redo (Ctrl+Y or Ctrl+Shift+Z)
+fn undo_shortcut_system(
+    keys: Res<Input<KeyCode>>,
+    mut sandbox: Query<&mut SandBox>,
+    mut history: ResMut<History>,
+    mut session: ResMut<Session>,
+    simulation: Res<Simulation>,
+) {
+    let control = keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    if !control {
+        return;
+    }
+    let Ok(mut sandbox) = sandbox.get_single_mut() else {
+        return;
+    };
+    if keys.just_pressed(KeyCode::Y) || (shift && keys.just_pressed(KeyCode::Z)) {
+        redo(&mut history, &mut sandbox, &mut session, &simulation);
+    } else if keys.just_pressed(KeyCode::Z) {
+        undo(&mut history, &mut sandbox, &mut session, &simulation);
+    }
+}
+
+// Keyboard shortcut for exporting a PNG
+#[cfg(not(target_family = "wasm"))]
+fn export_shortcut_system(
+    keys: Res<Input<KeyCode>>,
+    sandbox: Query<&SandBox>,
+    render_settings: Res<RenderSettings>,
+    mut gui: ResMut<SandboxGui>,
+) {
+    if keys.just_pressed(KeyCode::F12) {
+        if let Ok(sandbox) = sandbox.get_single() {
+            gui.file_status = export_png(sandbox, render_settings.overlay, &gui);
+        }
+    }
+}
+
 fn add_icon(egui_contexts: &mut EguiContexts, name: &str, image_data: &[u8]) -> TextureHandle {
     let image = image::load_from_memory(image_data).unwrap();
     let size = [image.width() as _, image.height() as _];
@@ -581,7 +855,7 @@ fn element_button(ui: &mut Ui, gui: &mut SandboxGui, element: Element) -> Respon
     let (rect, response) = ui.allocate_exact_size(Vec2::new(SIZE, SIZE), egui::Sense::click());
 
     if ui.is_rect_visible(rect) {
-        let mut mesh = Mesh::with_texture(gui.element_icons[element as usize].id());
+        let mut mesh = Mesh::with_texture(gui.element_icons[element.id() as usize].id());
         mesh.add_rect_with_uv(
             rect,
             Rect::from_min_max(Pos2::new(0.0, 0.0), Pos2::new(1.0, 1.0)),
@@ -648,7 +922,7 @@ pub fn generate_element_image(
     }
 
     egui_context.ctx_mut().load_texture(
-        format!("element_{}", element as u8),
+        format!("element_{}", element.id()),
         img,
         Default::default(),
     )
@@ -0,0 +1,161 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContexts};
+use egui::{Align2, Color32, FontId, Id, LayerId, Pos2, Rect, Stroke};
+
+use falling_rust::sandbox::*;
+
+use crate::interface::gui::{GuiMode, SandboxGui};
+use crate::interface::pointer_input::PointerInputState;
+
+/// Shows the state of the cell under the pointer while inspecting, and of pinned cells
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Inspector>()
+            .add_systems(Update, inspector_system);
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct Inspector {
+    // Cells that are shown until unpinned, in the order they were pinned
+    pub pinned: Vec<(usize, usize)>,
+}
+
+impl Inspector {
+    // Pin a cell, or unpin it when it was pinned already
+    pub fn toggle_pin(&mut self, x: usize, y: usize) {
+        if let Some(index) = self.pinned.iter().position(|&pinned| pinned == (x, y)) {
+            self.pinned.remove(index);
+        } else {
+            self.pinned.push((x, y));
+        }
+    }
+}
+
+fn inspector_system(
+    mut egui_contexts: EguiContexts,
+    mut inspector: ResMut<Inspector>,
+    gui: Res<SandboxGui>,
+    pointer: Res<PointerInputState>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    camera: Query<(&Camera, &GlobalTransform)>,
+    sandbox: Query<&SandBox>,
+) {
+    let Ok(sandbox) = sandbox.get_single() else {
+        // Sandbox not active, so skip this
+        return;
+    };
+    // Pinned cells may be outside a sandbox that was replaced by a smaller one
+    inspector
+        .pinned
+        .retain(|&(x, y)| x < sandbox.width() && y < sandbox.height());
+
+    let ctx = egui_contexts.ctx_mut();
+    if gui.mode == GuiMode::Inspect && !ctx.is_pointer_over_area() {
+        let (x, y) = (pointer.world_position.x, pointer.world_position.y);
+        if x >= 0.0 && x < sandbox.width() as f32 && y >= 0.0 && y < sandbox.height() as f32 {
+            let (x, y) = (x as usize, y as usize);
+            // Clicking or tapping a cell pins it
+            if mouse_buttons.just_pressed(MouseButton::Left) {
+                inspector.toggle_pin(x, y);
+            }
+            let position = Pos2::new(pointer.position.x + 16.0, pointer.position.y + 16.0);
+            egui::show_tooltip_at(ctx, Id::new("inspected_cell"), Some(position), |ui| {
+                cell_grid(ui, "inspected_cell_grid", sandbox, x, y);
+                ui.label("Click to pin");
+            });
+        }
+    }
+
+    if inspector.pinned.is_empty() {
+        return;
+    }
+
+    // Mark the pinned cells with their number
+    let (camera, camera_transform) = camera.single();
+    let painter = ctx.layer_painter(LayerId::background());
+    for (number, &(x, y)) in inspector.pinned.iter().enumerate() {
+        let center = Vec3::new(
+            x as f32 + 0.5 - (sandbox.width() / 2) as f32,
+            (sandbox.height() / 2) as f32 - y as f32 - 0.5,
+            0.0,
+        );
+        let Some(center) = camera.world_to_viewport(camera_transform, center) else {
+            continue;
+        };
+        let center = Pos2::new(center.x, center.y);
+        painter.rect_stroke(
+            Rect::from_center_size(center, egui::vec2(8.0, 8.0)),
+            0.0,
+            Stroke::new(2.0, Color32::WHITE),
+        );
+        painter.text(
+            center + egui::vec2(6.0, -6.0),
+            Align2::LEFT_BOTTOM,
+            (number + 1).to_string(),
+            FontId::proportional(14.0),
+            Color32::WHITE,
+        );
+    }
+
+    let mut unpin = None;
+    let mut unpin_all = false;
+    egui::Window::new("Pinned cells")
+        .resizable(false)
+        .show(ctx, |ui| {
+            for (number, &(x, y)) in inspector.pinned.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.strong(format!("{}", number + 1));
+                    if ui.button("Unpin").clicked() {
+                        unpin = Some(number);
+                    }
+                });
+                cell_grid(ui, ("pinned_cell_grid", x, y), sandbox, x, y);
+                ui.separator();
+            }
+            if ui.button("Unpin all").clicked() {
+                unpin_all = true;
+            }
+        });
+    if unpin_all {
+        inspector.pinned.clear();
+    } else if let Some(number) = unpin {
+        inspector.pinned.remove(number);
+    }
+}
+
+// Table of the state of a cell
+fn cell_grid(ui: &mut egui::Ui, id: impl std::hash::Hash, sandbox: &SandBox, x: usize, y: usize) {
+    let cell = sandbox.get(x, y);
+    let cell_type = element_type(cell.element);
+    let flags = cell_type.flag_names().collect::<Vec<_>>();
+    egui::Grid::new(id).num_columns(2).show(ui, |ui| {
+        ui.label("Position");
+        ui.label(format!("{}, {}", x, y));
+        ui.end_row();
+        ui.label("Element");
+        ui.label(cell_type.name);
+        ui.end_row();
+        ui.label("Form");
+        ui.label(format!("{:?}", cell_type.form));
+        ui.end_row();
+        ui.label("Strength");
+        ui.label(cell.strength.to_string());
+        ui.end_row();
+        ui.label("Variant");
+        ui.label(cell.variant.to_string());
+        ui.end_row();
+        ui.label("Temperature");
+        ui.label(format!("{} °C", { cell.temperature }));
+        ui.end_row();
+        ui.label("Flags");
+        ui.label(if flags.is_empty() {
+            "None".to_string()
+        } else {
+            flags.join(", ")
+        });
+        ui.end_row();
+    });
+}
@@ -1,12 +1,17 @@
 use bevy::prelude::*;
+use falling_rust::history::History;
+use falling_rust::toolbox::ToolBox;
+
 use crate::interface::gui::GuiPlugin;
+use crate::interface::inspector::InspectorPlugin;
 use crate::interface::pointer_input::PointerInputPlugin;
-use crate::interface::toolbox::ToolBox;
+use crate::interface::session::SessionPlugin;
 
 mod fill_browser;
 mod gui;
+mod inspector;
 mod pointer_input;
-mod toolbox;
+mod session;
 
 pub struct InterfacePlugin;
 
@@ -14,7 +19,10 @@ impl Plugin for InterfacePlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(GuiPlugin)
             .add_plugins(PointerInputPlugin)
-            .init_resource::<ToolBox>();
+            .add_plugins(SessionPlugin)
+            .add_plugins(InspectorPlugin)
+            .init_resource::<ToolBox>()
+            .init_resource::<History>();
 
         #[cfg(target_family = "wasm")]
         app.add_plugins(FillBrowserWindowPlugin);
@@ -1,4 +1,5 @@
 use bevy::{
+    ecs::system::SystemParam,
     input::{
         ButtonState,
         mouse::{MouseButtonInput, MouseWheel},
@@ -8,9 +9,10 @@ use bevy::{
 };
 use bevy_egui::EguiContexts;
 
-use crate::interface::gui::{GuiMode, SandboxGui};
-use crate::interface::toolbox::ToolBox;
-use crate::sandbox::{Element, SandBox};
+use falling_rust::replay::ReplayEvent;
+use falling_rust::sandbox::{Element, SandBox};
+
+use crate::interface::gui::{GuiMode, SandboxGui, SimulationResources};
 
 /// Handles both mouse and touch input for the sandbox editor
 pub struct PointerInputPlugin;
@@ -32,19 +34,32 @@ pub struct PointerInputState {
     pub world_position: Vec2,
 }
 
+// Mouse events since the last frame
+#[derive(SystemParam)]
+pub struct PointerEvents<'w, 's> {
+    pub mouse_button_input: EventReader<'w, 's, MouseButtonInput>,
+    pub cursor_moved: EventReader<'w, 's, CursorMoved>,
+    pub mouse_wheel: EventReader<'w, 's, MouseWheel>,
+}
+
 pub fn pointer_input(
     mut mouse: ResMut<PointerInputState>,
-    mut mouse_button_input_events: EventReader<MouseButtonInput>,
-    mut cursor_moved_events: EventReader<CursorMoved>,
-    mut mouse_wheel_events: EventReader<MouseWheel>,
+    mut events: PointerEvents,
     mut camera: Query<(&Camera, &mut Transform, &GlobalTransform)>,
     mut egui_context: EguiContexts,
-    mut toolbox: ResMut<ToolBox>,
     mut sandbox: Query<&mut SandBox>,
     gui: Res<SandboxGui>,
+    resources: SimulationResources,
 ) {
+    let SimulationResources {
+        mut toolbox,
+        simulation,
+        mut session,
+        mut history,
+    } = resources;
+
     // Determine button state
-    for event in mouse_button_input_events.read() {
+    for event in events.mouse_button_input.read() {
         if event.button == MouseButton::Left {
             mouse.left_button_down = event.state == ButtonState::Pressed;
         }
@@ -58,7 +73,7 @@ pub fn pointer_input(
 
     // Record latest position
     let last_position = mouse.position;
-    for event in cursor_moved_events.read() {
+    for event in events.cursor_moved.read() {
         mouse.position = event.position;
     }
     mouse.drag_movement = if mouse.left_button_down || mouse.middle_button_down {
@@ -69,7 +84,7 @@ pub fn pointer_input(
 
     // Check mouse wheel
     let mut wheel_y = 0.0;
-    for event in mouse_wheel_events.read() {
+    for event in events.mouse_wheel.read() {
         wheel_y += event.y;
     }
 
@@ -83,6 +98,7 @@ pub fn pointer_input(
         mouse.left_button_down = false;
         mouse.middle_button_down = false;
         mouse.right_button_down = false;
+        history.end_stroke();
         return;
     }
 
@@ -124,16 +140,26 @@ pub fn pointer_input(
         transform.translation.y = transform.translation.y.clamp(-half_height, half_height);
     }
 
-    // Edit the world
-    if gui.mode != GuiMode::MoveView {
+    // A stroke lasts from pressing a button until releasing it
+    if !mouse.left_button_down && !mouse.right_button_down {
+        history.end_stroke();
+    }
+
+    // Edit the world (but not while replaying a session or inspecting cells)
+    if !matches!(gui.mode, GuiMode::MoveView | GuiMode::Inspect) && !session.is_replaying() {
         let (x, y) = (mouse.world_position.x, mouse.world_position.y);
         if x > 0.0 && x < sandbox.width() as f32 && y > 0.0 && y < sandbox.height() as f32 {
+            let (x, y) = (x.floor() as usize, y.floor() as usize);
             if mouse.left_button_down {
-                toolbox.apply(&mut sandbox, x.floor() as usize, y.floor() as usize);
+                history.begin_stroke();
+                session.record(&simulation, ReplayEvent::apply(&toolbox, x, y));
+                toolbox.apply_tracked(&mut sandbox, x, y, history.stroke_mut());
             } else if mouse.right_button_down {
                 let element = toolbox.element;
                 toolbox.element = Element::Air;
-                toolbox.apply(&mut sandbox, x.floor() as usize, y.floor() as usize);
+                history.begin_stroke();
+                session.record(&simulation, ReplayEvent::apply(&toolbox, x, y));
+                toolbox.apply_tracked(&mut sandbox, x, y, history.stroke_mut());
                 toolbox.element = element;
             }
         }
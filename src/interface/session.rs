@@ -0,0 +1,148 @@
+use bevy::{prelude::*, render::render_resource::Extent3d};
+
+use falling_rust::replay::*;
+use falling_rust::sandbox::*;
+use falling_rust::simulation::*;
+use falling_rust::toolbox::ToolBox;
+
+/// Recording of editing sessions and replaying them
+pub struct SessionPlugin;
+
+impl Plugin for SessionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Session>()
+            .add_systems(Update, replay_system.before(simulation_system));
+    }
+}
+
+#[derive(Resource)]
+pub struct Session {
+    pub recorder: Recorder,
+    // Active replay, with its own toolbox so the user's tool selection is left alone
+    pub replay: Option<(ReplayPlayer, ToolBox)>,
+    pub file_name: String,
+    pub status: String,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            recorder: Recorder::default(),
+            replay: None,
+            file_name: "session.frr".to_string(),
+            status: String::new(),
+        }
+    }
+}
+
+impl Session {
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    // Record an event at the current tick of the simulation, if recording
+    pub fn record(&mut self, simulation: &Simulation, event: ReplayEvent) {
+        self.recorder.record(simulation.tick, event);
+    }
+
+    pub fn start_recording(
+        &mut self,
+        sandbox: &mut SandBox,
+        simulation: &Simulation,
+        toolbox: &ToolBox,
+    ) {
+        self.recorder.start(sandbox, simulation, toolbox);
+        self.status = "Recording...".to_string();
+    }
+
+    // Stop recording and write the recording to the session file
+    #[cfg(not(target_family = "wasm"))]
+    pub fn stop_recording(&mut self, simulation: &Simulation) {
+        if let Some(recording) = self.recorder.stop(simulation) {
+            self.status = match save_recording(&recording, &self.file_name) {
+                Ok(()) => format!("Saved {}", self.file_name),
+                Err(error) => format!("Save failed: {}", error),
+            };
+        }
+    }
+
+    // Load the session file and reset the sandbox and simulation to its starting point
+    #[cfg(not(target_family = "wasm"))]
+    pub fn start_replay(
+        &mut self,
+        sandbox: &mut SandBox,
+        image: &mut Image,
+        simulation: &mut Simulation,
+    ) {
+        let started = load_recording(&self.file_name).and_then(|recording| {
+            let player = ReplayPlayer::new(recording);
+            let start = player.start()?;
+            Ok((player, start))
+        });
+        match started {
+            Ok((player, (replay_sandbox, replay_simulation, replay_toolbox))) => {
+                replace_sandbox(sandbox, image, replay_sandbox);
+                *simulation = replay_simulation;
+                self.replay = Some((player, replay_toolbox));
+                self.status = format!("Replaying {}", self.file_name);
+            }
+            Err(error) => self.status = format!("Replay failed: {}", error),
+        }
+    }
+}
+
+// Feeds the events of an active replay into the simulation, before it is stepped
+fn replay_system(
+    mut session: ResMut<Session>,
+    mut sandbox: Query<(&mut SandBox, &Handle<Image>)>,
+    mut images: ResMut<Assets<Image>>,
+    mut simulation: ResMut<Simulation>,
+) {
+    let Ok((mut sandbox, image_handle)) = sandbox.get_single_mut() else {
+        return;
+    };
+    let Some((player, toolbox)) = session.replay.as_mut() else {
+        return;
+    };
+    let result = player.apply_events(&mut sandbox, &mut simulation, toolbox);
+    let finished = player.is_finished(&simulation);
+
+    // A replaced sandbox may differ in size from the texture
+    let image = images.get_mut(image_handle).unwrap();
+    let size = image.texture_descriptor.size;
+    if size.width as usize != sandbox.width() || size.height as usize != sandbox.height() {
+        image.resize(Extent3d {
+            width: sandbox.width() as u32,
+            height: sandbox.height() as u32,
+            depth_or_array_layers: 1,
+        });
+    }
+
+    match result {
+        Err(error) => {
+            session.status = format!("Replay failed: {}", error);
+            session.replay = None;
+        }
+        Ok(()) if finished => {
+            session.status = "Replay finished".to_string();
+            session.replay = None;
+        }
+        Ok(()) => {}
+    }
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn save_recording(recording: &Recording, file_name: &str) -> Result<(), ReplayError> {
+    use std::io::Write;
+
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(file_name)?);
+    recording.save_to(&mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn load_recording(file_name: &str) -> Result<Recording, ReplayError> {
+    let mut reader = std::io::BufReader::new(std::fs::File::open(file_name)?);
+    Recording::load_from(&mut reader)
+}
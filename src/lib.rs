@@ -0,0 +1,12 @@
+//! Falling-sand simulation core.
+//!
+//! The sandbox, elements and simulation are plain Rust and can be used without Bevy. The `bevy`
+//! feature (enabled by default) adds the components, resources and systems used by the
+//! interactive application.
+
+pub mod history;
+pub mod pseudo_random;
+pub mod replay;
+pub mod sandbox;
+pub mod simulation;
+pub mod toolbox;
@@ -3,16 +3,13 @@
 
 use bevy::{prelude::*, window::WindowResolution};
 
-use render::render_system;
-use sandbox::*;
+use falling_rust::sandbox::{install_element_file, spawn_sandbox, SandBox, ELEMENT_FILE_NAME};
+use falling_rust::simulation::{Simulation, simulation_system};
+use render::{render_system, SandBoxRenderPlugin};
 
 use crate::interface::InterfacePlugin;
-use crate::simulation::{Simulation, simulation_system};
 
-mod pseudo_random;
 mod render;
-mod sandbox;
-mod simulation;
 mod interface;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, SystemSet)]
@@ -21,6 +18,7 @@ pub enum SystemOrderLabel {
 }
 
 fn main() {
+    load_elements();
     App::new()
         .add_plugins((
             DefaultPlugins
@@ -34,7 +32,8 @@ fn main() {
                     ..default()
                 })
                 .set(ImagePlugin::default_nearest()),
-            InterfacePlugin
+            InterfacePlugin,
+            SandBoxRenderPlugin,
         ))
         .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
         .init_resource::<Simulation>()
@@ -43,7 +42,21 @@ fn main() {
         .run();
 }
 
+// Elements can be added or changed without recompiling using an element definitions file in the
+// working directory. This must happen before any element is used.
+fn load_elements() {
+    #[cfg(not(target_family = "wasm"))]
+    if std::path::Path::new(ELEMENT_FILE_NAME).exists() {
+        if let Err(error) = install_element_file(ELEMENT_FILE_NAME) {
+            eprintln!(
+                "Using built-in elements, {} is invalid: {}",
+                ELEMENT_FILE_NAME, error
+            );
+        }
+    }
+}
+
 fn setup(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
     commands.spawn(Camera2dBundle::default());
-    spawn_sandbox(commands, images.as_mut(), 256, 256);
+    spawn_sandbox(commands, images.as_mut(), SandBox::new(256, 256));
 }
@@ -9,7 +9,16 @@ impl PseudoRandom {
         Self { next: 12345 }
     }
 
+    pub fn with_seed(seed: u64) -> Self {
+        // Xorshift gets stuck on zero, so avoid that state
+        let next = (mix(seed) >> 32) as u32;
+        Self {
+            next: if next == 0 { 12345 } else { next },
+        }
+    }
+
     #[inline(always)]
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> u32 {
         self.next ^= self.next << 13;
         self.next ^= self.next >> 17;
@@ -17,3 +26,36 @@ impl PseudoRandom {
         self.next
     }
 }
+
+impl Default for PseudoRandom {
+    fn default() -> Self {
+        PseudoRandom::new()
+    }
+}
+
+// Random number for a cell in a specific simulation step. This does not depend on the order in
+// which cells are updated, so skipping or reordering cells does not change the outcome.
+#[inline(always)]
+pub fn cell_random(seed: u64, tick: u64, x: usize, y: usize) -> u32 {
+    let key = seed
+        ^ tick.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (x as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (y as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    (mix(key) >> 32) as u32
+}
+
+// Random number derived from another one, independent of other numbers derived from it with
+// another index. E.g. for separate chances with each neighbor of a cell.
+#[inline(always)]
+pub fn derived_random(random: u32, index: u32) -> u32 {
+    (mix((random as u64) << 32 | index as u64) >> 32) as u32
+}
+
+// Finalizer of the SplitMix64 generator, scrambles all bits of the input
+#[inline(always)]
+fn mix(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
@@ -1,34 +1,206 @@
 use bevy::prelude::*;
+use bevy::render::render_asset::{prepare_assets, RenderAssets};
+use bevy::render::render_resource::{
+    Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect,
+};
+use bevy::render::renderer::RenderQueue;
+use bevy::render::{Extract, ExtractSchedule, Render, RenderApp};
 use bevy::utils::Instant;
 
-use crate::sandbox::*;
+use falling_rust::sandbox::*;
 
-// "Render" the world by copying the element cells to pixels on a texture
+// Weight of the last render in the averages of render statistics
+const RENDER_AVERAGE_WEIGHT: f64 = 0.05;
+
+// Draws the sandbox on its texture. Only the areas of the sandbox that changed are drawn again and
+// written to the texture, so a paused or sleeping sandbox costs next to nothing.
+pub struct SandBoxRenderPlugin;
+
+impl Plugin for SandBoxRenderPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RenderSettings>();
+        if let Ok(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<TextureUploads>()
+                .add_systems(ExtractSchedule, extract_texture_uploads)
+                .add_systems(Render, upload_textures.after(prepare_assets::<Image>));
+        }
+    }
+}
+
+// The pixels of the sandbox texture, kept here because changing the image asset would upload the
+// whole texture again
+#[derive(Component)]
+pub struct SandBoxPixels {
+    pub data: Vec<u8>,
+    // Areas drawn this frame, which still have to be written to the texture
+    uploads: Vec<DirtyRect>,
+    // Average time taken to draw a cell in seconds, to estimate the cost of drawing all cells
+    cell_time: f64,
+}
+
+// How the sandbox is drawn
+#[derive(Resource, Clone, Copy, PartialEq)]
+pub struct RenderSettings {
+    // Debug overlay the sandbox is drawn with, if any
+    pub overlay: Option<Overlay>,
+    // Whether glowing cells light up the cells around them, which takes more time to draw
+    pub glow: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            overlay: None,
+            // Off in the browser, which is often used on slower devices
+            glow: !cfg!(target_family = "wasm"),
+        }
+    }
+}
+
+// Pixels to write to textures in the render world
+#[derive(Resource, Default)]
+struct TextureUploads(Vec<TextureUpload>);
+
+struct TextureUpload {
+    image: AssetId<Image>,
+    area: DirtyRect,
+    data: Vec<u8>,
+}
+
+// "Render" the world by copying the changed element cells to pixels
 pub fn render_system(
-    mut images: ResMut<Assets<Image>>,
-    mut sandbox: Query<(&mut SandBox, &Handle<Image>)>,
+    mut commands: Commands,
+    settings: Res<RenderSettings>,
+    mut sandbox: Query<(Entity, &mut SandBox, Option<&mut SandBoxPixels>)>,
 ) {
-    let sandbox = sandbox.get_single_mut();
-    if sandbox.is_err() {
+    let Ok((entity, mut sandbox, pixels)) = sandbox.get_single_mut() else {
         // Sandbox not active, so skip this
         return;
-    }
-    let (mut sandbox, image_handle) = sandbox.unwrap();
+    };
 
     let start = Instant::now();
 
-    let image = images.get_mut(image_handle).unwrap();
-    for y in 0..sandbox.height() {
-        for x in 0..sandbox.width() {
-            let color = element_type(sandbox.get_mut(x, y).element).color;
-            let index = (x + y * sandbox.width()) * 4;
-            image.data[index] = color.0;
-            image.data[index + 1] = color.1;
-            image.data[index + 2] = color.2;
-            image.data[index + 3] = 255;
+    let size = sandbox.width() * sandbox.height() * 4;
+    let mut new_pixels = None;
+    let pixels = match pixels {
+        Some(pixels) => pixels.into_inner(),
+        None => new_pixels.insert(SandBoxPixels {
+            data: vec![0; size],
+            uploads: Vec::new(),
+            cell_time: 0.0,
+        }),
+    };
+    if pixels.data.len() != size {
+        // The sandbox was replaced by one of another size
+        pixels.data = vec![0; size];
+        sandbox.mark_all_dirty();
+    }
+    if settings.is_changed()
+        || settings
+            .overlay
+            .is_some_and(|overlay| overlay.redraws_all())
+    {
+        sandbox.mark_all_dirty();
+    }
+    // Overlays are drawn without glow, to show exactly the state of the cells
+    let glow = settings.glow && settings.overlay.is_none();
+    pixels.uploads.clear();
+    let mut rendered_cells = 0;
+    for area in sandbox.dirty_rects() {
+        // Changed cells change the glow on the cells around them as well
+        let area = if glow { sandbox.glow_area(area) } else { area };
+        sandbox.render_area(area, settings.overlay, &mut pixels.data);
+        if glow {
+            sandbox.render_glow(area, &mut pixels.data);
         }
+        pixels.uploads.push(area);
+        rendered_cells += area.width() * area.height();
     }
+    sandbox.clear_dirty_rects();
 
     let duration = Instant::now() - start;
+    if rendered_cells > 0 {
+        let cell_time = duration.as_secs_f64() / rendered_cells as f64;
+        pixels.cell_time = if pixels.cell_time == 0.0 {
+            cell_time
+        } else {
+            average(pixels.cell_time, cell_time)
+        };
+    }
+    let full_render_time = pixels.cell_time * (sandbox.width() * sandbox.height()) as f64;
+    let saved_ms = (full_render_time - duration.as_secs_f64()).max(0.0) * 1000.0;
+    sandbox.render_time_saved_ms = average(sandbox.render_time_saved_ms, saved_ms);
     sandbox.render_time_ms = duration.as_millis();
-}
\ No newline at end of file
+    sandbox.rendered_cells = rendered_cells;
+
+    if let Some(pixels) = new_pixels {
+        commands.entity(entity).insert(pixels);
+    }
+}
+
+// Running average of a render statistic
+fn average(average: f64, value: f64) -> f64 {
+    average + (value - average) * RENDER_AVERAGE_WEIGHT
+}
+
+// Copy the areas drawn this frame to the render world
+fn extract_texture_uploads(
+    mut uploads: ResMut<TextureUploads>,
+    sandbox: Extract<Query<(&SandBox, &SandBoxPixels, &Handle<Image>)>>,
+) {
+    uploads.0.clear();
+    for (sandbox, pixels, image) in sandbox.iter() {
+        for &area in &pixels.uploads {
+            let mut data = Vec::with_capacity(area.width() * area.height() * 4);
+            for y in area.min_y..=area.max_y {
+                let row = y * sandbox.width();
+                data.extend_from_slice(
+                    &pixels.data[(row + area.min_x) * 4..(row + area.max_x + 1) * 4],
+                );
+            }
+            uploads.0.push(TextureUpload {
+                image: image.id(),
+                area,
+                data,
+            });
+        }
+    }
+}
+
+// Write the uploads to their textures, after the textures of new or replaced images were created
+fn upload_textures(
+    mut uploads: ResMut<TextureUploads>,
+    images: Res<RenderAssets<Image>>,
+    render_queue: Res<RenderQueue>,
+) {
+    for upload in uploads.0.drain(..) {
+        let Some(image) = images.get(upload.image) else {
+            continue;
+        };
+        let (width, height) = (upload.area.width() as u32, upload.area.height() as u32);
+        render_queue.write_texture(
+            ImageCopyTexture {
+                texture: &image.texture,
+                mip_level: 0,
+                origin: Origin3d {
+                    x: upload.area.min_x as u32,
+                    y: upload.area.min_y as u32,
+                    z: 0,
+                },
+                aspect: TextureAspect::All,
+            },
+            &upload.data,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(width * 4),
+                rows_per_image: None,
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
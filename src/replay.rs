@@ -0,0 +1,441 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use crate::sandbox::*;
+use crate::simulation::*;
+use crate::toolbox::*;
+
+// Replay file layout (all numbers little endian):
+// - magic "FRRP"
+// - format version (u16)
+// - seed, start tick and end tick (u64 each)
+// - toolbox random state (u32), running (u8) and visited state (u8)
+// - initial sandbox in the sandbox file format, prefixed by its length (u32)
+// - event count (u32), followed by the events, each starting with its tick (u64) and kind (u8)
+// Version 1 files lack the temperature of set cells, they get the temperature of their element.
+const MAGIC: &[u8; 4] = b"FRRP";
+pub const REPLAY_FORMAT_VERSION: u16 = 2;
+
+const EVENT_APPLY: u8 = 0;
+const EVENT_SET_RUNNING: u8 = 1;
+const EVENT_STEP: u8 = 2;
+const EVENT_SANDBOX_REPLACED: u8 = 3;
+const EVENT_SET_CELLS: u8 = 4;
+
+// Something that happened during an editing session that influences the simulation
+#[derive(Clone, Debug)]
+pub enum ReplayEvent {
+    // ToolBox::apply was called with this tool configuration
+    Apply {
+        tool: Tool,
+        element: Element,
+        tool_size: usize,
+        x: usize,
+        y: usize,
+    },
+    // The simulation was paused or resumed
+    SetRunning(bool),
+    // A single step was requested while paused
+    Step,
+    // A new sandbox was created or loaded, stored in the sandbox file format
+    SandBoxReplaced(Vec<u8>),
+    // Cells were set directly, e.g. by undo or redo
+    SetCells(Vec<(usize, usize, Cell)>),
+}
+
+impl ReplayEvent {
+    pub fn apply(toolbox: &ToolBox, x: usize, y: usize) -> Self {
+        ReplayEvent::Apply {
+            tool: toolbox.tool,
+            element: toolbox.element,
+            tool_size: toolbox.tool_size,
+            x,
+            y,
+        }
+    }
+
+    pub fn sandbox_replaced(sandbox: &SandBox) -> Self {
+        ReplayEvent::SandBoxReplaced(save_to_vec(sandbox))
+    }
+}
+
+// An event and the simulation tick at which it happened
+#[derive(Clone, Debug)]
+pub struct RecordedEvent {
+    pub tick: u64,
+    pub event: ReplayEvent,
+}
+
+// A recorded editing session, starting from a snapshot of the sandbox
+#[derive(Clone, Debug)]
+pub struct Recording {
+    pub seed: u64,
+    pub start_tick: u64,
+    pub end_tick: u64,
+    pub toolbox_random: u32,
+    pub running: bool,
+    pub visited_state: bool,
+    pub sandbox: Vec<u8>,
+    pub events: Vec<RecordedEvent>,
+}
+
+// Records events while a recording is active
+#[derive(Default)]
+pub struct Recorder {
+    recording: Option<Recording>,
+}
+
+impl Recorder {
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // Start a new recording from the current state
+    pub fn start(&mut self, sandbox: &mut SandBox, simulation: &Simulation, toolbox: &ToolBox) {
+        // A replay starts with all chunks awake and all cells visited, so do the same here to
+        // simulate identically
+        sandbox.wake_all();
+        sandbox.reset_visited_state(sandbox.is_visited_state());
+        self.recording = Some(Recording {
+            seed: simulation.seed,
+            start_tick: simulation.tick,
+            end_tick: simulation.tick,
+            toolbox_random: toolbox.random.next,
+            running: simulation.running,
+            visited_state: sandbox.is_visited_state(),
+            sandbox: save_to_vec(sandbox),
+            events: Vec::new(),
+        });
+    }
+
+    // Stop recording, returning the recording if one was active
+    pub fn stop(&mut self, simulation: &Simulation) -> Option<Recording> {
+        let mut recording = self.recording.take()?;
+        recording.end_tick = simulation.tick;
+        Some(recording)
+    }
+
+    // Record an event, if recording
+    pub fn record(&mut self, tick: u64, event: ReplayEvent) {
+        if let Some(recording) = &mut self.recording {
+            recording.events.push(RecordedEvent { tick, event });
+        }
+    }
+}
+
+// Plays back a recording by feeding its events to a simulation at the ticks they happened
+pub struct ReplayPlayer {
+    recording: Recording,
+    position: usize,
+}
+
+impl ReplayPlayer {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            position: 0,
+        }
+    }
+
+    // The state at the start of the recording
+    pub fn start(&self) -> Result<(SandBox, Simulation, ToolBox), ReplayError> {
+        let mut sandbox = load_from_slice(&self.recording.sandbox)?;
+        sandbox.reset_visited_state(self.recording.visited_state);
+        let mut simulation = Simulation::with_seed(self.recording.seed);
+        simulation.tick = self.recording.start_tick;
+        simulation.running = self.recording.running;
+        let mut toolbox = ToolBox::default();
+        toolbox.random.next = self.recording.toolbox_random;
+        Ok((sandbox, simulation, toolbox))
+    }
+
+    // Apply all events that happened up to the current tick of the simulation
+    pub fn apply_events(
+        &mut self,
+        sandbox: &mut SandBox,
+        simulation: &mut Simulation,
+        toolbox: &mut ToolBox,
+    ) -> Result<(), ReplayError> {
+        while let Some(recorded) = self.recording.events.get(self.position) {
+            if recorded.tick > simulation.tick {
+                break;
+            }
+            match &recorded.event {
+                ReplayEvent::Apply {
+                    tool,
+                    element,
+                    tool_size,
+                    x,
+                    y,
+                } => {
+                    toolbox.tool = *tool;
+                    toolbox.element = *element;
+                    toolbox.tool_size = *tool_size;
+                    if *x < sandbox.width() && *y < sandbox.height() {
+                        toolbox.apply(sandbox, *x, *y);
+                    }
+                }
+                ReplayEvent::SetRunning(running) => simulation.running = *running,
+                ReplayEvent::Step => simulation.step = true,
+                ReplayEvent::SandBoxReplaced(data) => *sandbox = load_from_slice(data)?,
+                ReplayEvent::SetCells(cells) => {
+                    for &(x, y, cell) in cells.iter() {
+                        if x < sandbox.width() && y < sandbox.height() {
+                            sandbox.set_cell(x, y, cell);
+                        }
+                    }
+                }
+            }
+            self.position += 1;
+        }
+        Ok(())
+    }
+
+    pub fn is_finished(&self, simulation: &Simulation) -> bool {
+        self.position >= self.recording.events.len() && simulation.tick >= self.recording.end_tick
+    }
+
+    // Run the whole recording without interruption, returning the final state
+    pub fn run(mut self) -> Result<(SandBox, Simulation), ReplayError> {
+        let (mut sandbox, mut simulation, mut toolbox) = self.start()?;
+        loop {
+            self.apply_events(&mut sandbox, &mut simulation, &mut toolbox)?;
+            if self.is_finished(&simulation) {
+                break;
+            }
+            let tick = simulation.tick;
+            simulation_step(&mut simulation, &mut sandbox);
+            if simulation.tick == tick {
+                // Paused without any further events that could resume the simulation
+                break;
+            }
+        }
+        Ok((sandbox, simulation))
+    }
+}
+
+// Errors that can occur while reading or playing back a recording
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    UnknownEvent(u8),
+    UnknownTool(u8),
+    UnknownElement(u8),
+    SandBox(SandBoxFileError),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Io(error) => write!(f, "I/O error: {}", error),
+            ReplayError::InvalidMagic => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported replay file version {} (expected at most {})",
+                version, REPLAY_FORMAT_VERSION
+            ),
+            ReplayError::Truncated => write!(f, "replay file is truncated"),
+            ReplayError::UnknownEvent(id) => write!(f, "unknown replay event {}", id),
+            ReplayError::UnknownTool(id) => write!(f, "unknown tool id {}", id),
+            ReplayError::UnknownElement(id) => write!(f, "unknown element id {}", id),
+            ReplayError::SandBox(error) => write!(f, "invalid sandbox in replay: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReplayError::Io(error) => Some(error),
+            ReplayError::SandBox(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ReplayError {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            ReplayError::Truncated
+        } else {
+            ReplayError::Io(error)
+        }
+    }
+}
+
+impl From<SandBoxFileError> for ReplayError {
+    fn from(error: SandBoxFileError) -> Self {
+        ReplayError::SandBox(error)
+    }
+}
+
+impl Recording {
+    // Write the recording in the versioned replay file format
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&REPLAY_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&self.seed.to_le_bytes())?;
+        writer.write_all(&self.start_tick.to_le_bytes())?;
+        writer.write_all(&self.end_tick.to_le_bytes())?;
+        writer.write_all(&self.toolbox_random.to_le_bytes())?;
+        writer.write_all(&[self.running as u8, self.visited_state as u8])?;
+        write_data(writer, &self.sandbox)?;
+        writer.write_all(&(self.events.len() as u32).to_le_bytes())?;
+        for recorded in self.events.iter() {
+            writer.write_all(&recorded.tick.to_le_bytes())?;
+            match &recorded.event {
+                ReplayEvent::Apply {
+                    tool,
+                    element,
+                    tool_size,
+                    x,
+                    y,
+                } => {
+                    writer.write_all(&[EVENT_APPLY, *tool as u8, element.id()])?;
+                    writer.write_all(&(*tool_size as u32).to_le_bytes())?;
+                    writer.write_all(&(*x as u32).to_le_bytes())?;
+                    writer.write_all(&(*y as u32).to_le_bytes())?;
+                }
+                ReplayEvent::SetRunning(running) => {
+                    writer.write_all(&[EVENT_SET_RUNNING, *running as u8])?;
+                }
+                ReplayEvent::Step => {
+                    writer.write_all(&[EVENT_STEP])?;
+                }
+                ReplayEvent::SandBoxReplaced(data) => {
+                    writer.write_all(&[EVENT_SANDBOX_REPLACED])?;
+                    write_data(writer, data)?;
+                }
+                ReplayEvent::SetCells(cells) => {
+                    writer.write_all(&[EVENT_SET_CELLS])?;
+                    writer.write_all(&(cells.len() as u32).to_le_bytes())?;
+                    for &(x, y, cell) in cells.iter() {
+                        writer.write_all(&(x as u32).to_le_bytes())?;
+                        writer.write_all(&(y as u32).to_le_bytes())?;
+                        writer.write_all(&[cell.element.id(), cell.variant, cell.strength])?;
+                        writer.write_all(&{ cell.temperature }.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Read a recording written by save_to
+    pub fn load_from<R: Read>(reader: &mut R) -> Result<Recording, ReplayError> {
+        let magic: [u8; 4] = read_bytes(reader)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::InvalidMagic);
+        }
+        let version = u16::from_le_bytes(read_bytes(reader)?);
+        if version == 0 || version > REPLAY_FORMAT_VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        let seed = u64::from_le_bytes(read_bytes(reader)?);
+        let start_tick = u64::from_le_bytes(read_bytes(reader)?);
+        let end_tick = u64::from_le_bytes(read_bytes(reader)?);
+        let toolbox_random = u32::from_le_bytes(read_bytes(reader)?);
+        let [running, visited_state] = read_bytes(reader)?;
+        let sandbox = read_data(reader)?;
+        let event_count = u32::from_le_bytes(read_bytes(reader)?);
+        let mut events = Vec::new();
+        for _ in 0..event_count {
+            let tick = u64::from_le_bytes(read_bytes(reader)?);
+            let [kind] = read_bytes(reader)?;
+            let event = match kind {
+                EVENT_APPLY => {
+                    let [tool, element] = read_bytes(reader)?;
+                    ReplayEvent::Apply {
+                        tool: Tool::try_from(tool).map_err(ReplayError::UnknownTool)?,
+                        element: Element::try_from(element).map_err(ReplayError::UnknownElement)?,
+                        tool_size: u32::from_le_bytes(read_bytes(reader)?) as usize,
+                        x: u32::from_le_bytes(read_bytes(reader)?) as usize,
+                        y: u32::from_le_bytes(read_bytes(reader)?) as usize,
+                    }
+                }
+                EVENT_SET_RUNNING => {
+                    let [running] = read_bytes(reader)?;
+                    ReplayEvent::SetRunning(running != 0)
+                }
+                EVENT_STEP => ReplayEvent::Step,
+                EVENT_SANDBOX_REPLACED => ReplayEvent::SandBoxReplaced(read_data(reader)?),
+                EVENT_SET_CELLS => {
+                    let count = u32::from_le_bytes(read_bytes(reader)?);
+                    let mut cells = Vec::new();
+                    for _ in 0..count {
+                        let x = u32::from_le_bytes(read_bytes(reader)?) as usize;
+                        let y = u32::from_le_bytes(read_bytes(reader)?) as usize;
+                        let [element, variant, strength] = read_bytes(reader)?;
+                        let element =
+                            Element::try_from(element).map_err(ReplayError::UnknownElement)?;
+                        let temperature = if version >= 2 {
+                            i16::from_le_bytes(read_bytes(reader)?)
+                        } else {
+                            element_type(element).temperature
+                        };
+                        let cell = Cell {
+                            element,
+                            variant,
+                            strength,
+                            temperature,
+                        };
+                        cells.push((x, y, cell));
+                    }
+                    ReplayEvent::SetCells(cells)
+                }
+                _ => return Err(ReplayError::UnknownEvent(kind)),
+            };
+            events.push(RecordedEvent { tick, event });
+        }
+        Ok(Recording {
+            seed,
+            start_tick,
+            end_tick,
+            toolbox_random,
+            running: running != 0,
+            visited_state: visited_state != 0,
+            sandbox,
+            events,
+        })
+    }
+}
+
+fn save_to_vec(sandbox: &SandBox) -> Vec<u8> {
+    let mut data = Vec::new();
+    sandbox
+        .save_to(&mut data)
+        .expect("writing to memory cannot fail");
+    data
+}
+
+fn load_from_slice(data: &[u8]) -> Result<SandBox, ReplayError> {
+    let mut reader = data;
+    Ok(SandBox::load_from(&mut reader)?)
+}
+
+fn write_data<W: Write>(writer: &mut W, data: &[u8]) -> Result<(), ReplayError> {
+    writer.write_all(&(data.len() as u32).to_le_bytes())?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+fn read_data<R: Read>(reader: &mut R) -> Result<Vec<u8>, ReplayError> {
+    let length = u32::from_le_bytes(read_bytes(reader)?) as u64;
+    let mut data = Vec::new();
+    reader.take(length).read_to_end(&mut data)?;
+    if data.len() as u64 != length {
+        return Err(ReplayError::Truncated);
+    }
+    Ok(data)
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], ReplayError> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
@@ -1,18 +1,101 @@
+use crate::pseudo_random::cell_random;
 use crate::sandbox::*;
 
-// A cell that contains the state of a single pixel in the sand box.
-#[derive(Clone, Debug)]
+// Spark states of conductive cells, stored in the variant field
+pub const SPARK_NONE: u8 = 0;
+pub const SPARK_HEAD: u8 = 1;
+pub const SPARK_TAIL: u8 = 2;
+
+const SPARK_HEAD_COLOR: (u8, u8, u8) = (255, 255, 160);
+const SPARK_TAIL_COLOR: (u8, u8, u8) = (255, 160, 32);
+
+// A cell that contains the state of a single pixel in the sand box. Cells are packed into five
+// bytes so more of them fit in the caches. Whether a cell was visited is kept by the sandbox.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(C, packed)]
 pub struct Cell {
     // Element in this cell
     pub element: Element,
     // Generic data fields, usage depends on element
     pub variant: u8,
     pub strength: u8,
-    // Toggles each simulation step, to avoid duplicate simulation
-    pub visited: bool,
+    // Temperature in degrees Celsius
+    pub temperature: i16,
 }
 
 impl Cell {
+    // Color of the cell, showing sparks traveling through conductors
+    pub fn color(&self) -> (u8, u8, u8) {
+        let cell_type = element_type(self.element);
+        if cell_type.has_flag(FLAG_CONDUCTIVE) {
+            match self.variant {
+                SPARK_HEAD => return SPARK_HEAD_COLOR,
+                SPARK_TAIL => return SPARK_TAIL_COLOR,
+                _ => {}
+            }
+        }
+        cell_type.color
+    }
+
+    // Color of the cell as drawn at a position. Cells pick a color from the palette of their
+    // element and vary in brightness by position, so areas of an element do not look flat, and fade
+    // as they are used up or cool down.
+    pub fn shaded_color(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let cell_type = element_type(self.element);
+        if cell_type.has_flag(FLAG_CONDUCTIVE) && matches!(self.variant, SPARK_HEAD | SPARK_TAIL) {
+            return self.color();
+        }
+        if cell_type.palette.is_empty() && cell_type.color_noise == 0 && cell_type.fade.is_none() {
+            return cell_type.color;
+        }
+        // The same for every step, so cells that stay in place keep their look
+        let noise = cell_random(0, 0, x, y);
+        let mut color = if cell_type.palette.is_empty() {
+            cell_type.color
+        } else {
+            cell_type.palette[noise as usize % cell_type.palette.len()]
+        };
+        match cell_type.fade {
+            Some(Fade::Strength(faded)) if cell_type.strength > 0 => {
+                let used_up = cell_type.strength.saturating_sub(self.strength) as i32;
+                color = mix(color, faded, used_up, cell_type.strength as i32);
+            }
+            Some(Fade::Temperature(faded, cold)) if cold < cell_type.temperature => {
+                let range = cell_type.temperature as i32 - cold as i32;
+                let cooled =
+                    (cell_type.temperature as i32 - self.temperature as i32).clamp(0, range);
+                color = mix(color, faded, cooled, range);
+            }
+            _ => {}
+        }
+        if cell_type.color_noise > 0 {
+            let noise_range = cell_type.color_noise as i32;
+            let offset = (noise >> 16) as i32 % (2 * noise_range + 1) - noise_range;
+            color = (
+                (color.0 as i32 + offset).clamp(0, 255) as u8,
+                (color.1 as i32 + offset).clamp(0, 255) as u8,
+                (color.2 as i32 + offset).clamp(0, 255) as u8,
+            );
+        }
+        color
+    }
+
+    // Light the cell casts onto nearby cells, which dims along with the fade of cells that cool
+    // down
+    pub fn glow(&self) -> Option<(u8, u8, u8)> {
+        let cell_type = element_type(self.element);
+        let glow = cell_type.glow?;
+        match cell_type.fade {
+            Some(Fade::Temperature(_, cold)) if cold < cell_type.temperature => {
+                let range = cell_type.temperature as i32 - cold as i32;
+                let cooled =
+                    (cell_type.temperature as i32 - self.temperature as i32).clamp(0, range);
+                Some(mix(glow, (0, 0, 0), cooled, range))
+            }
+            _ => Some(glow),
+        }
+    }
+
     // Reduce strength and turn into the given element of strength is zero
     pub fn dissolve_to(&mut self, element: Element) -> bool {
         if self.strength > 0 {
@@ -25,3 +108,14 @@ impl Cell {
         }
     }
 }
+
+// Blend from one color towards another by amount out of total
+pub(super) fn mix(from: (u8, u8, u8), to: (u8, u8, u8), amount: i32, total: i32) -> (u8, u8, u8) {
+    let channel =
+        |from: u8, to: u8| (from as i32 + (to as i32 - from as i32) * amount / total) as u8;
+    (
+        channel(from.0, to.0),
+        channel(from.1, to.1),
+        channel(from.2, to.2),
+    )
+}
@@ -0,0 +1,116 @@
+// Width and height of the square chunks the sandbox is divided into
+pub const CHUNK_SIZE: usize = 32;
+// Number of steps without changes after which a chunk falls asleep
+pub const SLEEP_DELAY: u8 = 16;
+
+// Area of changed cells, in sandbox coordinates (inclusive)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DirtyRect {
+    pub min_x: usize,
+    pub min_y: usize,
+    pub max_x: usize,
+    pub max_y: usize,
+}
+
+impl DirtyRect {
+    pub fn width(&self) -> usize {
+        self.max_x - self.min_x + 1
+    }
+
+    pub fn height(&self) -> usize {
+        self.max_y - self.min_y + 1
+    }
+
+    pub(super) fn include(&mut self, x: usize, y: usize) {
+        self.min_x = self.min_x.min(x);
+        self.min_y = self.min_y.min(y);
+        self.max_x = self.max_x.max(x);
+        self.max_y = self.max_y.max(y);
+    }
+}
+
+// A square part of the sandbox. Chunks in which nothing happened for a while fall asleep and
+// are skipped by the simulation until something changes in or next to them.
+#[derive(Clone, Debug, Default)]
+pub struct Chunk {
+    idle_steps: u8,
+    dirty: Option<DirtyRect>,
+}
+
+impl Chunk {
+    pub fn is_awake(&self) -> bool {
+        self.idle_steps < SLEEP_DELAY
+    }
+
+    // Area changed since the dirty rectangles were last cleared
+    pub fn dirty_rect(&self) -> Option<DirtyRect> {
+        self.dirty
+    }
+
+    // Wake up the chunk, returns true if it was sleeping
+    pub(super) fn wake(&mut self) -> bool {
+        let was_sleeping = !self.is_awake();
+        self.idle_steps = 0;
+        was_sleeping
+    }
+
+    pub(super) fn mark_dirty(&mut self, x: usize, y: usize) {
+        match &mut self.dirty {
+            Some(dirty) => dirty.include(x, y),
+            None => {
+                self.dirty = Some(DirtyRect {
+                    min_x: x,
+                    min_y: y,
+                    max_x: x,
+                    max_y: y,
+                })
+            }
+        }
+    }
+
+    pub(super) fn mark_dirty_rect(&mut self, rect: DirtyRect) {
+        self.mark_dirty(rect.min_x, rect.min_y);
+        self.mark_dirty(rect.max_x, rect.max_y);
+    }
+
+    pub(super) fn clear_dirty(&mut self) {
+        self.dirty = None;
+    }
+
+    pub(super) fn end_step(&mut self) {
+        self.idle_steps = (self.idle_steps + 1).min(SLEEP_DELAY);
+    }
+}
+
+// Chunks to keep awake for a cell: its own chunk, and its neighbors when the cell is on an edge
+pub(super) fn chunks_around(
+    x: usize,
+    y: usize,
+    chunks_x: usize,
+    chunks_y: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let (chunk_x, chunk_y) = (x / CHUNK_SIZE, y / CHUNK_SIZE);
+    let min_x = if x.is_multiple_of(CHUNK_SIZE) {
+        chunk_x.saturating_sub(1)
+    } else {
+        chunk_x
+    };
+    let min_y = if y.is_multiple_of(CHUNK_SIZE) {
+        chunk_y.saturating_sub(1)
+    } else {
+        chunk_y
+    };
+    let max_x = if x % CHUNK_SIZE == CHUNK_SIZE - 1 {
+        chunk_x + 1
+    } else {
+        chunk_x
+    };
+    let max_y = if y % CHUNK_SIZE == CHUNK_SIZE - 1 {
+        chunk_y + 1
+    } else {
+        chunk_y
+    };
+    (min_y..=max_y.min(chunks_y - 1)).flat_map(move |chunk_y| {
+        (min_x..=max_x.min(chunks_x - 1)).map(move |chunk_x| (chunk_x, chunk_y))
+    })
+}
@@ -1,38 +1,66 @@
 use std::fmt;
+use std::sync::OnceLock;
 
-pub const ELEMENT_COUNT: usize = 27;
+use serde::Deserialize;
 
-// The different element types that live in a cell in the sand box
-#[repr(u8)]
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub enum Element {
-    Air = 0,
-    Sand = 1,
-    Rock = 2,
-    Water = 3,
-    Acid = 4,
-    Drain = 5,
-    Wood = 6,
-    Iron = 7,
-    Rust = 8,
-    Fire = 9,
-    Ash = 10,
-    Oil = 11,
-    Lava = 12,
-    Smoke = 13,
-    Life = 14,
-    Seed = 15,
-    Plant = 16,
-    TNT = 17,
-    Gunpowder = 18,
-    Fuse = 19,
-    Explosion = 20,
-    WaterSource = 21,
-    AcidSource = 22,
-    OilSource = 23,
-    FireSource = 24,
-    LavaSource = 25,
-    Indestructible = 26,
+pub const BUILTIN_ELEMENT_COUNT: usize = 37;
+// Element ids are stored as a byte
+pub const MAX_ELEMENT_COUNT: usize = 256;
+
+// The element that lives in a cell in the sand box, identified by its index in the element table.
+// The built-in elements have fixed ids, elements loaded from a file are numbered after them.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Element(u8);
+
+#[allow(non_upper_case_globals)]
+impl Element {
+    pub const Air: Element = Element(0);
+    pub const Sand: Element = Element(1);
+    pub const Rock: Element = Element(2);
+    pub const Water: Element = Element(3);
+    pub const Acid: Element = Element(4);
+    pub const Drain: Element = Element(5);
+    pub const Wood: Element = Element(6);
+    pub const Iron: Element = Element(7);
+    pub const Rust: Element = Element(8);
+    pub const Fire: Element = Element(9);
+    pub const Ash: Element = Element(10);
+    pub const Oil: Element = Element(11);
+    pub const Lava: Element = Element(12);
+    pub const Smoke: Element = Element(13);
+    pub const Life: Element = Element(14);
+    pub const Seed: Element = Element(15);
+    pub const Plant: Element = Element(16);
+    pub const TNT: Element = Element(17);
+    pub const Gunpowder: Element = Element(18);
+    pub const Fuse: Element = Element(19);
+    pub const Explosion: Element = Element(20);
+    pub const WaterSource: Element = Element(21);
+    pub const AcidSource: Element = Element(22);
+    pub const OilSource: Element = Element(23);
+    pub const FireSource: Element = Element(24);
+    pub const LavaSource: Element = Element(25);
+    pub const Indestructible: Element = Element(26);
+    pub const Ice: Element = Element(27);
+    pub const Steam: Element = Element(28);
+    pub const Glass: Element = Element(29);
+    pub const MoltenIron: Element = Element(30);
+    pub const Slag: Element = Element(31);
+    pub const Obsidian: Element = Element(32);
+    pub const Wire: Element = Element(33);
+    pub const Battery: Element = Element(34);
+    pub const Heater: Element = Element(35);
+    pub const Pump: Element = Element(36);
+
+    // Id as used in saved sandboxes
+    pub fn id(self) -> u8 {
+        self.0
+    }
+
+    // Element for an id that is known to be valid, without looking at the element definitions
+    pub(super) fn from_id(id: u8) -> Element {
+        Element(id)
+    }
 }
 
 pub const FLAG_DISSOLVES_IN_ACID: u32 = 0b00000000000000000000000000000001;
@@ -43,254 +71,923 @@ pub const FLAG_NUTRITIOUS: u32 = 0b00000000000000000000000000010000;
 pub const FLAG_WET: u32 = 0b00000000000000000000000000100000;
 pub const FLAG_ALLOW_PLANT: u32 = 0b00000000000000000000000001000000;
 pub const FLAG_IS_SOURCE: u32 = 0b00000000000000000000000010000000;
-pub const FLAG_IGNITES: u32 = 0b00000000000000000000000100000000;
 pub const FLAG_BLAST_RESISTANT: u32 = 0b00000000000000000000001000000000;
 pub const FLAG_ACIDIC: u32 = 0b00000000000000000000010000000000;
+pub const FLAG_CONDUCTIVE: u32 = 0b00000000000000000000100000000000;
+pub const FLAG_QUENCHES: u32 = 0b00000000000000000001000000000000;
+
+// Names of the flags, as used in element files
+pub const FLAG_NAMES: [(u32, &str); 12] = [
+    (FLAG_DISSOLVES_IN_ACID, "DissolvesInAcid"),
+    (FLAG_BURNS, "Burns"),
+    (FLAG_CAUSES_RUST, "CausesRust"),
+    (FLAG_TURNS_INTO_ASH, "TurnsIntoAsh"),
+    (FLAG_NUTRITIOUS, "Nutritious"),
+    (FLAG_WET, "Wet"),
+    (FLAG_ALLOW_PLANT, "AllowPlant"),
+    (FLAG_IS_SOURCE, "IsSource"),
+    (FLAG_BLAST_RESISTANT, "BlastResistant"),
+    (FLAG_ACIDIC, "Acidic"),
+    (FLAG_CONDUCTIVE, "Conductive"),
+    (FLAG_QUENCHES, "Quenches"),
+];
+
+// Temperature of the environment, which open air and steam slowly return to
+pub const AMBIENT_TEMPERATURE: i16 = 20;
+// Highest conductivity for which heat conduction stays stable (4 neighbors * 64 = 256)
+pub const MAX_CONDUCTIVITY: u8 = 64;
 
 // Definition of an element type
 #[derive(Clone, Debug)]
 pub struct ElementType {
+    pub name: &'static str,
     pub form: ElementForm,
+    // Generic strength of new cells. For liquids it is also the number of cells they flow sideways
+    // per step, at most CHUNK_REACH.
     pub strength: u8,
     pub weight: u8,
     pub color: (u8, u8, u8),
+    // Colors cells use instead of the color, picked by their position, unless empty
+    pub palette: &'static [(u8, u8, u8)],
+    // Largest change in brightness of cells, varying by their position
+    pub color_noise: u8,
+    // Color cells turn into as they are used up or cool down
+    pub fade: Option<Fade>,
+    // Color of the light cells cast onto nearby cells, if they give off light
+    pub glow: Option<(u8, u8, u8)>,
     pub flags: u32,
     pub source_element: Element,
+    // Temperature of newly created cells
+    pub temperature: i16,
+    // Resistance against temperature change, at least 1
+    pub heat_capacity: u8,
+    // Speed of heat exchange with neighbors, from 0 (insulator) to MAX_CONDUCTIVITY
+    pub conductivity: u8,
+    // Temperature at which the element starts to burn or explode
+    pub ignition_temperature: Option<i16>,
+    // Phase transitions when heated above or cooled below a temperature
+    pub above: Option<PhaseTransition>,
+    pub below: Option<PhaseTransition>,
+    // Whether the element can be picked for drawing
+    pub selectable: bool,
+}
+
+// The color a cell changes to gradually, starting from its own color
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum Fade {
+    // Reached when the strength drops from that of a new cell to zero, e.g. iron rusting
+    Strength((u8, u8, u8)),
+    // Reached when cooled down from the temperature of a new cell to the given temperature
+    Temperature((u8, u8, u8), i16),
+}
+
+// The element a cell turns into when its temperature passes a threshold, or the quenched element
+// instead when it touches a cell with the quenches flag, e.g. lava cooled by water
+#[derive(Clone, Copy, Debug)]
+pub struct PhaseTransition {
+    pub temperature: i16,
+    pub element: Element,
+    pub quenched: Option<Element>,
+}
+
+const fn transition(temperature: i16, element: Element) -> Option<PhaseTransition> {
+    Some(PhaseTransition {
+        temperature,
+        element,
+        quenched: None,
+    })
 }
 
 impl ElementType {
     pub fn has_flag(&self, flag: u32) -> bool {
         self.flags & flag > 0
     }
+
+    // Names of the flags of the element
+    pub fn flag_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        FLAG_NAMES
+            .iter()
+            .filter(|(flag, _)| self.has_flag(*flag))
+            .map(|(_, name)| *name)
+    }
+}
+
+// The element definitions, padded to the maximum number of elements so looking up any element id
+// needs no bounds check. Only the first count definitions are in use.
+struct ElementTable {
+    types: Box<[ElementType; MAX_ELEMENT_COUNT]>,
+    count: usize,
+}
+
+impl ElementTable {
+    fn new(mut element_types: Vec<ElementType>) -> Self {
+        let count = element_types.len();
+        element_types.resize(MAX_ELEMENT_COUNT, BUILTIN_ELEMENTS[0].clone());
+        ElementTable {
+            types: element_types.into_boxed_slice().try_into().unwrap(),
+            count,
+        }
+    }
+}
+
+static ELEMENT_TYPES: OnceLock<ElementTable> = OnceLock::new();
+
+fn element_table() -> &'static ElementTable {
+    ELEMENT_TYPES.get_or_init(|| ElementTable::new(BUILTIN_ELEMENTS.to_vec()))
 }
 
 #[inline(always)]
 pub fn element_type(element: Element) -> &'static ElementType {
-    &ELEMENTS[element as usize]
+    &element_table().types[element.0 as usize]
 }
 
-// All element definitions. Note that the order must be identical to that in the Element enum.
-pub static ELEMENTS: [ElementType; ELEMENT_COUNT] = [
+// All element definitions, indexed by element id. These are the built-in elements unless others
+// were installed at startup.
+pub fn element_types() -> &'static [ElementType] {
+    let table = element_table();
+    &table.types[..table.count]
+}
+
+// All elements, in order of their ids
+pub fn all_elements() -> impl Iterator<Item = Element> {
+    (0..element_types().len()).map(|id| Element(id as u8))
+}
+
+// Replace the element definitions, e.g. with those loaded from a file. This must happen before
+// the elements are first used and the built-in elements must keep their ids, so element specific
+// behaviour still applies to them. Returns the definitions when that is not the case.
+pub fn install_element_types(element_types: Vec<ElementType>) -> Result<(), Vec<ElementType>> {
+    if element_types.len() < BUILTIN_ELEMENT_COUNT || element_types.len() > MAX_ELEMENT_COUNT {
+        return Err(element_types);
+    }
+    let count = element_types.len();
+    ELEMENT_TYPES
+        .set(ElementTable::new(element_types))
+        .map_err(|table| table.types[..count].to_vec())
+}
+
+// Whether element definitions were installed or the built-in ones are in use, so no others can be
+// installed anymore
+pub fn element_types_installed() -> bool {
+    ELEMENT_TYPES.get().is_some()
+}
+
+// The built-in element definitions. Note that the order must be identical to the element ids.
+pub static BUILTIN_ELEMENTS: [ElementType; BUILTIN_ELEMENT_COUNT] = [
     // Air = 0
     ElementType {
+        name: "Air",
         form: ElementForm::Gas,
         strength: 1,
         weight: 128,
         color: (33, 122, 238),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
         flags: FLAG_ALLOW_PLANT,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 1,
+        conductivity: 2,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: false,
     }, //  Sand = 1,
     ElementType {
+        name: "Sand",
         form: ElementForm::Powder,
         strength: 8,
         weight: 1,
         color: (224, 198, 98),
+        palette: &[
+            (224, 198, 98),
+            (214, 186, 88),
+            (232, 208, 114),
+            (206, 180, 86),
+        ],
+        color_noise: 6,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_NUTRITIOUS | FLAG_ALLOW_PLANT,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 4,
+        conductivity: 8,
+        ignition_temperature: None,
+        above: transition(800, Element::Glass),
+        below: None,
+        selectable: true,
     }, // Rock = 2,
     ElementType {
+        name: "Rock",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (107, 104, 104),
+        palette: &[(107, 104, 104), (96, 94, 95), (118, 114, 112)],
+        color_noise: 6,
+        fade: None,
+        glow: None,
         flags: FLAG_BLAST_RESISTANT,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 8,
+        conductivity: 24,
+        ignition_temperature: None,
+        above: transition(1300, Element::Lava),
+        below: None,
+        selectable: true,
     }, // Water = 3,
     ElementType {
+        name: "Water",
         form: ElementForm::Liquid,
         strength: 12,
         weight: 128,
         color: (16, 16, 128),
-        flags: FLAG_CAUSES_RUST | FLAG_WET | FLAG_ALLOW_PLANT,
+        palette: &[],
+        color_noise: 3,
+        fade: None,
+        glow: None,
+        flags: FLAG_CAUSES_RUST | FLAG_WET | FLAG_ALLOW_PLANT | FLAG_CONDUCTIVE | FLAG_QUENCHES,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 8,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: transition(100, Element::Steam),
+        below: transition(-2, Element::Ice),
+        selectable: true,
     }, // Acid = 4,
     ElementType {
+        name: "Acid",
         form: ElementForm::Liquid,
         strength: 10,
         weight: 32,
         color: (182, 255, 5),
+        palette: &[],
+        color_noise: 4,
+        fade: None,
+        glow: None,
         flags: FLAG_ACIDIC,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 4,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
     }, // Drain = 5,
     ElementType {
+        name: "Drain",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (0, 0, 0),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
         flags: 0,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 8,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
     }, // Wood = 6,
     ElementType {
+        name: "Wood",
         form: ElementForm::Solid,
         strength: 16,
         weight: 1,
         color: (122, 57, 0),
+        palette: &[(122, 57, 0), (110, 50, 0), (134, 66, 8)],
+        color_noise: 6,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_BURNS | FLAG_TURNS_INTO_ASH | FLAG_BLAST_RESISTANT,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 2,
+        conductivity: 16,
+        ignition_temperature: Some(250),
+        above: None,
+        below: None,
+        selectable: true,
     }, // Iron = 7,
     ElementType {
+        name: "Iron",
         form: ElementForm::Solid,
         strength: 64,
         weight: 1,
         color: (160, 157, 157),
-        flags: FLAG_BLAST_RESISTANT,
+        palette: &[],
+        color_noise: 4,
+        fade: Some(Fade::Strength((128, 72, 40))),
+        glow: None,
+        flags: FLAG_BLAST_RESISTANT | FLAG_CONDUCTIVE,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 4,
+        conductivity: 48,
+        ignition_temperature: None,
+        above: transition(1400, Element::MoltenIron),
+        below: None,
+        selectable: true,
     }, // Rust = 8,
     ElementType {
+        name: "Rust",
         form: ElementForm::Powder,
         strength: 1,
         weight: 1,
         color: (115, 50, 2),
+        palette: &[],
+        color_noise: 10,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_CAUSES_RUST,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 4,
+        conductivity: 8,
+        ignition_temperature: None,
+        above: transition(900, Element::Slag),
+        below: None,
+        selectable: false,
     }, // Fire = 9,
     ElementType {
+        name: "Fire",
         form: ElementForm::Gas,
         strength: 64,
         weight: 64,
         color: (255, 225, 136),
-        flags: FLAG_IGNITES,
+        palette: &[],
+        color_noise: 0,
+        fade: Some(Fade::Temperature((200, 60, 16), 200)),
+        glow: Some((160, 80, 20)),
+        flags: 0,
         source_element: Element::Air,
+        temperature: 800,
+        heat_capacity: 4,
+        conductivity: 32,
+        ignition_temperature: None,
+        above: None,
+        below: transition(200, Element::Smoke),
+        selectable: true,
     }, // Ash = 10,
     ElementType {
+        name: "Ash",
         form: ElementForm::Powder,
         strength: 16,
         weight: 1,
         color: (214, 220, 234),
+        palette: &[],
+        color_noise: 8,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_NUTRITIOUS | FLAG_ALLOW_PLANT,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 2,
+        conductivity: 4,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: false,
     }, // Oil = 11,
     ElementType {
+        name: "Oil",
         form: ElementForm::Liquid,
         strength: 10,
         weight: 64,
         color: (64, 32, 64),
+        palette: &[],
+        color_noise: 3,
+        fade: None,
+        glow: None,
         flags: FLAG_BURNS,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 2,
+        conductivity: 8,
+        ignition_temperature: Some(250),
+        above: None,
+        below: None,
+        selectable: true,
     }, // Lava = 12,
     ElementType {
+        name: "Lava",
         form: ElementForm::Liquid,
         strength: 4,
         weight: 192,
         color: (180, 64, 16),
-        flags: FLAG_IGNITES,
+        palette: &[],
+        color_noise: 8,
+        fade: Some(Fade::Temperature((80, 28, 16), 900)),
+        glow: Some((120, 40, 8)),
+        flags: 0,
         source_element: Element::Air,
+        temperature: 1200,
+        heat_capacity: 8,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: None,
+        below: Some(PhaseTransition {
+            temperature: 900,
+            element: Element::Rock,
+            quenched: Some(Element::Obsidian),
+        }),
+        selectable: true,
     }, // Smoke = 13,
     ElementType {
+        name: "Smoke",
         form: ElementForm::Gas,
         strength: 32,
         weight: 32,
         color: (8, 8, 8),
+        palette: &[],
+        color_noise: 0,
+        fade: Some(Fade::Strength((33, 122, 238))),
+        glow: None,
         flags: 0,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 1,
+        conductivity: 2,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: false,
     }, // Life = 14,
     ElementType {
+        name: "Life",
         form: ElementForm::Solid,
         strength: 2,
         weight: 1,
         color: (210, 255, 210),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_BURNS | FLAG_TURNS_INTO_ASH,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 2,
+        conductivity: 8,
+        ignition_temperature: Some(150),
+        above: None,
+        below: None,
+        selectable: true,
     }, // Seed = 15,
     ElementType {
+        name: "Seed",
         form: ElementForm::Powder,
         strength: 32,
         weight: 1,
         color: (170, 220, 130),
+        palette: &[],
+        color_noise: 6,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_NUTRITIOUS,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 2,
+        conductivity: 8,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
     }, // Plant = 16,
     ElementType {
+        name: "Plant",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (60, 200, 30),
+        palette: &[(60, 200, 30), (46, 180, 24), (78, 214, 46)],
+        color_noise: 6,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_BURNS | FLAG_NUTRITIOUS,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 2,
+        conductivity: 8,
+        ignition_temperature: Some(200),
+        above: None,
+        below: None,
+        selectable: false,
     }, // TNT = 17,
     ElementType {
+        name: "TNT",
         form: ElementForm::Solid,
         strength: 3,
         weight: 1,
         color: (200, 32, 16),
+        palette: &[],
+        color_noise: 6,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 1,
+        conductivity: 32,
+        ignition_temperature: Some(250),
+        above: None,
+        below: None,
+        selectable: true,
     }, // Gunpowder = 18,
     ElementType {
+        name: "Gunpowder",
         form: ElementForm::Powder,
         strength: 2,
         weight: 2,
         color: (122, 21, 3),
+        palette: &[],
+        color_noise: 8,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 1,
+        conductivity: 32,
+        ignition_temperature: Some(200),
+        above: None,
+        below: None,
+        selectable: true,
     }, // Fuse = 19,
     ElementType {
+        name: "Fuse",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (211, 80, 91),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
         flags: FLAG_DISSOLVES_IN_ACID | FLAG_BURNS,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 1,
+        conductivity: 32,
+        ignition_temperature: Some(150),
+        above: None,
+        below: None,
+        selectable: true,
     }, // Explosion = 20,
     ElementType {
+        name: "Explosion",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (245, 220, 200),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: Some((200, 160, 100)),
         flags: 0,
         source_element: Element::Air,
+        temperature: 1500,
+        heat_capacity: 1,
+        conductivity: 32,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: false,
     }, // WaterSource = 21,
     ElementType {
+        name: "WaterSource",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (16, 16, 255),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
         flags: FLAG_IS_SOURCE,
         source_element: Element::Water,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 8,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
     }, // AcidSource = 22
     ElementType {
+        name: "AcidSource",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (160, 255, 64),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
         flags: FLAG_IS_SOURCE,
         source_element: Element::Acid,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 8,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
     }, // OilSource = 23,
     ElementType {
+        name: "OilSource",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (32, 8, 32),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
         flags: FLAG_IS_SOURCE,
         source_element: Element::Oil,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 8,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: false,
     }, // FireSource = 24,
     ElementType {
+        name: "FireSource",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (255, 255, 163),
-        flags: FLAG_IS_SOURCE | FLAG_IGNITES,
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: Some((160, 80, 20)),
+        flags: FLAG_IS_SOURCE,
         source_element: Element::Fire,
+        temperature: 800,
+        heat_capacity: 1,
+        conductivity: 32,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
     }, // LavaSource = 25,
     ElementType {
+        name: "LavaSource",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (255, 128, 32),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: Some((120, 40, 8)),
         flags: FLAG_IS_SOURCE,
         source_element: Element::Lava,
+        temperature: 1200,
+        heat_capacity: 8,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
     }, // Indestructible = 26,
     ElementType {
+        name: "Indestructible",
         form: ElementForm::Solid,
         strength: 1,
         weight: 1,
         color: (64, 40, 40),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
         flags: 0,
         source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 1,
+        conductivity: 0,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: false,
+    }, // Ice = 27,
+    ElementType {
+        name: "Ice",
+        form: ElementForm::Solid,
+        strength: 1,
+        weight: 1,
+        color: (190, 230, 250),
+        palette: &[],
+        color_noise: 4,
+        fade: None,
+        glow: None,
+        flags: FLAG_QUENCHES,
+        source_element: Element::Air,
+        temperature: -20,
+        heat_capacity: 8,
+        conductivity: 32,
+        ignition_temperature: None,
+        above: transition(2, Element::Water),
+        below: None,
+        selectable: true,
+    }, // Steam = 28,
+    ElementType {
+        name: "Steam",
+        form: ElementForm::Gas,
+        strength: 16,
+        weight: 48,
+        color: (200, 210, 230),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
+        flags: FLAG_CAUSES_RUST | FLAG_QUENCHES,
+        source_element: Element::Air,
+        temperature: 110,
+        heat_capacity: 2,
+        conductivity: 4,
+        ignition_temperature: None,
+        above: None,
+        // Latent heat is not simulated, so steam only condenses well below the boiling point
+        below: transition(60, Element::Water),
+        selectable: true,
+    }, // Glass = 29,
+    ElementType {
+        name: "Glass",
+        form: ElementForm::Solid,
+        strength: 8,
+        weight: 1,
+        color: (180, 220, 215),
+        palette: &[],
+        color_noise: 3,
+        fade: None,
+        glow: None,
+        flags: 0,
+        source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 4,
+        conductivity: 8,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
+    }, // MoltenIron = 30,
+    ElementType {
+        name: "MoltenIron",
+        form: ElementForm::Liquid,
+        strength: 3,
+        weight: 224,
+        color: (255, 170, 60),
+        palette: &[],
+        color_noise: 6,
+        fade: Some(Fade::Temperature((190, 80, 30), 1400)),
+        glow: Some((140, 60, 20)),
+        flags: 0,
+        source_element: Element::Air,
+        temperature: 1800,
+        heat_capacity: 4,
+        conductivity: 48,
+        ignition_temperature: None,
+        above: None,
+        below: transition(1350, Element::Iron),
+        selectable: true,
+    }, // Slag = 31,
+    ElementType {
+        name: "Slag",
+        form: ElementForm::Powder,
+        strength: 4,
+        weight: 1,
+        color: (84, 72, 64),
+        palette: &[],
+        color_noise: 10,
+        fade: None,
+        glow: None,
+        flags: 0,
+        source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 4,
+        conductivity: 8,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
+    }, // Obsidian = 32,
+    ElementType {
+        name: "Obsidian",
+        form: ElementForm::Solid,
+        strength: 32,
+        weight: 1,
+        color: (36, 24, 48),
+        palette: &[],
+        color_noise: 4,
+        fade: None,
+        glow: None,
+        flags: FLAG_BLAST_RESISTANT,
+        source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 8,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: transition(1300, Element::Lava),
+        below: None,
+        selectable: true,
+    }, // Wire = 33,
+    ElementType {
+        name: "Wire",
+        form: ElementForm::Solid,
+        strength: 1,
+        weight: 1,
+        color: (184, 115, 51),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
+        flags: FLAG_CONDUCTIVE,
+        source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 4,
+        conductivity: 48,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
+    }, // Battery = 34,
+    ElementType {
+        name: "Battery",
+        form: ElementForm::Solid,
+        strength: 1,
+        weight: 1,
+        color: (48, 96, 48),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
+        flags: 0,
+        source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 4,
+        conductivity: 8,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
+    }, // Heater = 35,
+    ElementType {
+        name: "Heater",
+        form: ElementForm::Solid,
+        strength: 1,
+        weight: 1,
+        color: (160, 48, 40),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
+        flags: 0,
+        source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 2,
+        conductivity: 48,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
+    }, // Pump = 36,
+    ElementType {
+        name: "Pump",
+        form: ElementForm::Solid,
+        strength: 1,
+        weight: 1,
+        color: (72, 96, 136),
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
+        flags: 0,
+        source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 4,
+        conductivity: 16,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
     },
 ];
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
 pub enum ElementForm {
     Solid,
     Powder,
@@ -300,6 +997,25 @@ pub enum ElementForm {
 
 impl fmt::Display for Element {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        write!(f, "{}", element_type(*self).name)
+    }
+}
+
+impl fmt::Debug for Element {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", element_type(*self).name)
+    }
+}
+
+impl TryFrom<u8> for Element {
+    type Error = u8;
+
+    // Convert an element id (as used in saved sandboxes) back into an element
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        if (id as usize) < element_types().len() {
+            Ok(Element(id))
+        } else {
+            Err(id)
+        }
     }
 }
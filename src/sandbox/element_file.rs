@@ -0,0 +1,574 @@
+use std::{fmt, io};
+
+use ron::extensions::Extensions;
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+
+use crate::sandbox::*;
+
+// Element definitions file, a RON list of elements such as:
+//
+// [
+//     (
+//         name: "Salt",
+//         form: Powder,
+//         color: (240, 240, 232),
+//         weight: 2,
+//         flags: [DissolvesInAcid],
+//         above: (temperature: 800, element: "Lava"),
+//         reactions: [(touching: "Water", chance: 20, product: "Air")],
+//     ),
+// ]
+//
+// A definition with the name of an existing element changes only the given properties of that
+// element, other names add a new element. New elements need at least a form and a color, other
+// properties default to those of a simple inert element. Elements are referred to by name.
+// Reactions are those of the element with its neighbors, touching either an element or any
+// element with a flag (touching_flag). They take precedence over the built-in reactions.
+// A phase transition can have a quenched element, which cells turn into instead while touching an
+// element with the Quenches flag.
+// A script gives an element custom behaviour written in Rhai, see ElementScript. Its budget
+// (script_budget) is the number of operations it may use per cell, its step budget
+// (script_step_budget) the number it may use for all cells of a chunk in a step.
+pub const ELEMENT_FILE_NAME: &str = "elements.ron";
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ElementDefinition {
+    name: String,
+    form: Option<ElementForm>,
+    color: Option<(u8, u8, u8)>,
+    palette: Option<Vec<(u8, u8, u8)>>,
+    color_noise: Option<u8>,
+    fade: Option<Fade>,
+    glow: Option<(u8, u8, u8)>,
+    strength: Option<u8>,
+    weight: Option<u8>,
+    flags: Option<Vec<ElementFlag>>,
+    source_element: Option<String>,
+    temperature: Option<i16>,
+    heat_capacity: Option<u8>,
+    conductivity: Option<u8>,
+    ignition_temperature: Option<i16>,
+    above: Option<TransitionDefinition>,
+    below: Option<TransitionDefinition>,
+    selectable: Option<bool>,
+    reactions: Option<Vec<ReactionDefinition>>,
+    script: Option<String>,
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    script_budget: Option<u64>,
+    #[cfg_attr(not(feature = "scripting"), allow(dead_code))]
+    script_step_budget: Option<u64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReactionDefinition {
+    touching: Option<String>,
+    touching_flag: Option<ElementFlag>,
+    chance: u32,
+    strength_divisor: Option<u32>,
+    product: Option<String>,
+    touching_product: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransitionDefinition {
+    temperature: i16,
+    element: String,
+    quenched: Option<String>,
+}
+
+// A flag given by its name in FLAG_NAMES, such as DissolvesInAcid
+#[derive(Clone, Copy)]
+struct ElementFlag(u32);
+
+impl ElementFlag {
+    fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl<'de> Deserialize<'de> for ElementFlag {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FlagVisitor;
+
+        impl Visitor<'_> for FlagVisitor {
+            type Value = ElementFlag;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "the name of an element flag")
+            }
+
+            fn visit_str<E: de::Error>(self, name: &str) -> Result<ElementFlag, E> {
+                FLAG_NAMES
+                    .iter()
+                    .find(|(_, flag_name)| *flag_name == name)
+                    .map(|(flag, _)| ElementFlag(*flag))
+                    .ok_or_else(|| {
+                        let names: Vec<&str> = FLAG_NAMES.iter().map(|(_, name)| *name).collect();
+                        E::custom(format!(
+                            "unknown flag `{}`, expected one of {}",
+                            name,
+                            names.join(", ")
+                        ))
+                    })
+            }
+        }
+
+        deserializer.deserialize_identifier(FlagVisitor)
+    }
+}
+
+// Errors that can occur while loading element definitions
+#[derive(Debug)]
+pub enum ElementFileError {
+    Io(io::Error),
+    Parse(ron::error::SpannedError),
+    DuplicateElement(String),
+    TooManyElements,
+    MissingProperty {
+        element: String,
+        property: &'static str,
+    },
+    UnknownElement {
+        element: String,
+        property: &'static str,
+        name: String,
+    },
+    InvalidValue {
+        element: String,
+        property: &'static str,
+        reason: String,
+    },
+    AlreadyInstalled,
+}
+
+impl fmt::Display for ElementFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ElementFileError::Io(error) => write!(f, "I/O error: {}", error),
+            ElementFileError::Parse(error) => write!(f, "invalid definition at {}", error),
+            ElementFileError::DuplicateElement(name) => {
+                write!(f, "element \"{}\" is defined more than once", name)
+            }
+            ElementFileError::TooManyElements => {
+                write!(f, "too many elements (at most {})", MAX_ELEMENT_COUNT)
+            }
+            ElementFileError::MissingProperty { element, property } => write!(
+                f,
+                "new element \"{}\" needs a {} property",
+                element, property
+            ),
+            ElementFileError::UnknownElement {
+                element,
+                property,
+                name,
+            } => write!(
+                f,
+                "{} of element \"{}\" refers to unknown element \"{}\"",
+                property, element, name
+            ),
+            ElementFileError::InvalidValue {
+                element,
+                property,
+                reason,
+            } => write!(f, "{} of element \"{}\" {}", property, element, reason),
+            ElementFileError::AlreadyInstalled => {
+                write!(f, "elements can only be installed before they are used")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ElementFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ElementFileError::Io(error) => Some(error),
+            ElementFileError::Parse(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ElementFileError {
+    fn from(error: io::Error) -> Self {
+        ElementFileError::Io(error)
+    }
+}
+
+impl From<ron::error::SpannedError> for ElementFileError {
+    fn from(error: ron::error::SpannedError) -> Self {
+        ElementFileError::Parse(error)
+    }
+}
+
+// The built-in elements and reactions with those of an element definitions file applied
+pub struct ElementDefinitions {
+    pub element_types: Vec<ElementType>,
+    pub reactions: Vec<Reaction>,
+    // Scripts indexed by element id
+    #[cfg(feature = "scripting")]
+    pub scripts: Vec<Option<ElementScript>>,
+}
+
+impl ElementDefinitions {
+    // Use these definitions, which must happen before elements are first used. Nothing is
+    // installed when any part can no longer be, so elements never get the reactions or scripts of
+    // other definitions.
+    pub fn install(self) -> Result<(), ElementFileError> {
+        #[cfg(feature = "scripting")]
+        let scripts_installed = scripts_installed();
+        #[cfg(not(feature = "scripting"))]
+        let scripts_installed = false;
+        if element_types_installed() || reactions_installed() || scripts_installed {
+            return Err(ElementFileError::AlreadyInstalled);
+        }
+        install_element_types(self.element_types)
+            .map_err(|_| ElementFileError::AlreadyInstalled)?;
+        install_reactions(self.reactions).map_err(|_| ElementFileError::AlreadyInstalled)?;
+        #[cfg(feature = "scripting")]
+        install_scripts(self.scripts).map_err(|_| ElementFileError::AlreadyInstalled)?;
+        Ok(())
+    }
+}
+
+// Load element definitions from a file and install them
+pub fn install_element_file(path: &str) -> Result<(), ElementFileError> {
+    parse_element_definitions(&std::fs::read_to_string(path)?)?.install()
+}
+
+pub fn parse_element_definitions(text: &str) -> Result<ElementDefinitions, ElementFileError> {
+    let options = ron::Options::default().with_default_extension(Extensions::IMPLICIT_SOME);
+    let definitions: Vec<ElementDefinition> = options.from_str(text)?;
+
+    // Assign ids first, so elements can refer to elements defined after them
+    let mut names: Vec<&str> = BUILTIN_ELEMENTS
+        .iter()
+        .map(|element| element.name)
+        .collect();
+    let mut ids = Vec::new();
+    for (index, definition) in definitions.iter().enumerate() {
+        if definition.name.is_empty() {
+            return Err(ElementFileError::InvalidValue {
+                element: definition.name.clone(),
+                property: "name",
+                reason: "must not be empty".to_string(),
+            });
+        }
+        if definitions[..index]
+            .iter()
+            .any(|other| other.name == definition.name)
+        {
+            return Err(ElementFileError::DuplicateElement(definition.name.clone()));
+        }
+        let id = match names.iter().position(|name| *name == definition.name) {
+            Some(id) => id,
+            None => {
+                names.push(&definition.name);
+                names.len() - 1
+            }
+        };
+        ids.push(id);
+    }
+    if names.len() > MAX_ELEMENT_COUNT {
+        return Err(ElementFileError::TooManyElements);
+    }
+
+    let mut element_types = BUILTIN_ELEMENTS.to_vec();
+    let mut reactions = Vec::new();
+    #[cfg(feature = "scripting")]
+    let mut scripts: Vec<Option<ElementScript>> = names.iter().map(|_| None).collect();
+    for (definition, id) in definitions.iter().zip(ids) {
+        if id < element_types.len() {
+            element_types[id] = apply_definition(element_types[id].clone(), definition, &names)?;
+        } else {
+            let new_type = new_element_type(definition)?;
+            element_types.push(apply_definition(new_type, definition, &names)?);
+        }
+        for reaction in definition.reactions.iter().flatten() {
+            reactions.push(parse_reaction(
+                Element::from_id(id as u8),
+                reaction,
+                definition,
+                &names,
+            )?);
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(source) = &definition.script {
+            scripts[id] = Some(parse_script(source, definition)?);
+        }
+        #[cfg(not(feature = "scripting"))]
+        if definition.script.is_some() {
+            return Err(ElementFileError::InvalidValue {
+                element: definition.name.clone(),
+                property: "script",
+                reason: "needs a build with the scripting feature".to_string(),
+            });
+        }
+    }
+    check_transitions(&element_types)?;
+    reactions.extend_from_slice(&BUILTIN_REACTIONS);
+    Ok(ElementDefinitions {
+        element_types,
+        reactions,
+        #[cfg(feature = "scripting")]
+        scripts,
+    })
+}
+
+// Cells keep their temperature when they change into another element, which should not turn back
+// at that temperature. E.g. ice must melt above a higher temperature than water freezes below.
+fn check_transitions(element_types: &[ElementType]) -> Result<(), ElementFileError> {
+    for element_type in element_types {
+        let invalid = |property, comparison, target: &ElementType| ElementFileError::InvalidValue {
+            element: element_type.name.to_string(),
+            property,
+            reason: format!(
+                "must have a temperature {} of \"{}\", which it turns into",
+                comparison, target.name
+            ),
+        };
+        if let Some(above) = element_type.above {
+            let target = &element_types[above.element.id() as usize];
+            if matches!(target.below, Some(below) if below.temperature >= above.temperature) {
+                return Err(invalid("above", "higher than below", target));
+            }
+        }
+        if let Some(below) = element_type.below {
+            let target = &element_types[below.element.id() as usize];
+            if matches!(target.above, Some(above) if above.temperature <= below.temperature) {
+                return Err(invalid("below", "lower than above", target));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "scripting")]
+fn parse_script(
+    source: &str,
+    definition: &ElementDefinition,
+) -> Result<ElementScript, ElementFileError> {
+    let invalid = |property, reason: String| ElementFileError::InvalidValue {
+        element: definition.name.clone(),
+        property,
+        reason,
+    };
+    let budget = definition.script_budget.unwrap_or(DEFAULT_SCRIPT_BUDGET);
+    if budget == 0 {
+        return Err(invalid("script_budget", "must be at least 1".to_string()));
+    }
+    let step_budget = definition
+        .script_step_budget
+        .unwrap_or(DEFAULT_SCRIPT_STEP_BUDGET);
+    if step_budget < budget {
+        return Err(invalid(
+            "script_step_budget",
+            format!("must be at least the script_budget of {}", budget),
+        ));
+    }
+    ElementScript::compile(source, budget, step_budget)
+        .map_err(|error| invalid("script", format!("does not compile: {}", error)))
+}
+
+fn parse_reaction(
+    element: Element,
+    reaction: &ReactionDefinition,
+    definition: &ElementDefinition,
+    names: &[&str],
+) -> Result<Reaction, ElementFileError> {
+    let invalid = |property, reason: &str| ElementFileError::InvalidValue {
+        element: definition.name.clone(),
+        property,
+        reason: reason.to_string(),
+    };
+    let touching = match (&reaction.touching, reaction.touching_flag) {
+        (Some(name), None) => {
+            Reactant::Element(find_element(names, definition, "reaction touching", name)?)
+        }
+        (None, Some(flag)) => Reactant::Flag(flag.bits()),
+        _ => {
+            return Err(invalid(
+                "reaction",
+                "needs either a touching or a touching_flag property",
+            ))
+        }
+    };
+    if reaction.chance == 0 {
+        return Err(invalid("reaction chance", "must be at least 1"));
+    }
+    if reaction.strength_divisor == Some(0) {
+        return Err(invalid("reaction strength_divisor", "must be at least 1"));
+    }
+    let product = |property, name: &Option<String>| {
+        name.as_ref()
+            .map(|name| find_element(names, definition, property, name))
+            .transpose()
+    };
+    Ok(Reaction {
+        reactant: Reactant::Element(element),
+        touching,
+        chance: reaction.chance,
+        strength_divisor: reaction.strength_divisor,
+        product: product("reaction product", &reaction.product)?,
+        touching_product: product("reaction touching_product", &reaction.touching_product)?,
+    })
+}
+
+fn find_element(
+    names: &[&str],
+    definition: &ElementDefinition,
+    property: &'static str,
+    name: &str,
+) -> Result<Element, ElementFileError> {
+    names
+        .iter()
+        .position(|known| *known == name)
+        .map(|id| Element::from_id(id as u8))
+        .ok_or_else(|| ElementFileError::UnknownElement {
+            element: definition.name.clone(),
+            property,
+            name: name.to_string(),
+        })
+}
+
+// Base for a new element, with the properties a new element must define
+fn new_element_type(definition: &ElementDefinition) -> Result<ElementType, ElementFileError> {
+    let missing = |property| ElementFileError::MissingProperty {
+        element: definition.name.clone(),
+        property,
+    };
+    Ok(ElementType {
+        // Element names live as long as the element definitions, which is until the program ends
+        name: Box::leak(definition.name.clone().into_boxed_str()),
+        form: definition.form.ok_or_else(|| missing("form"))?,
+        strength: 1,
+        weight: 1,
+        color: definition.color.ok_or_else(|| missing("color"))?,
+        palette: &[],
+        color_noise: 0,
+        fade: None,
+        glow: None,
+        flags: 0,
+        source_element: Element::Air,
+        temperature: AMBIENT_TEMPERATURE,
+        heat_capacity: 1,
+        conductivity: 0,
+        ignition_temperature: None,
+        above: None,
+        below: None,
+        selectable: true,
+    })
+}
+
+fn apply_definition(
+    mut element_type: ElementType,
+    definition: &ElementDefinition,
+    names: &[&str],
+) -> Result<ElementType, ElementFileError> {
+    let element = |property, name: &str| find_element(names, definition, property, name);
+    let invalid = |property, reason: &str| ElementFileError::InvalidValue {
+        element: definition.name.clone(),
+        property,
+        reason: reason.to_string(),
+    };
+
+    if let Some(form) = definition.form {
+        element_type.form = form;
+    }
+    if let Some(color) = definition.color {
+        element_type.color = color;
+    }
+    if let Some(palette) = &definition.palette {
+        // Like names, palettes live until the program ends
+        element_type.palette = Box::leak(palette.clone().into_boxed_slice());
+    }
+    if let Some(color_noise) = definition.color_noise {
+        element_type.color_noise = color_noise;
+    }
+    if definition.fade.is_some() {
+        element_type.fade = definition.fade;
+    }
+    if definition.glow.is_some() {
+        element_type.glow = definition.glow;
+    }
+    if let Some(strength) = definition.strength {
+        element_type.strength = strength;
+    }
+    if let Some(weight) = definition.weight {
+        element_type.weight = weight;
+    }
+    if let Some(flags) = &definition.flags {
+        element_type.flags = flags.iter().fold(0, |bits, flag| bits | flag.bits());
+    }
+    if let Some(name) = &definition.source_element {
+        element_type.source_element = element("source_element", name)?;
+    }
+    if let Some(temperature) = definition.temperature {
+        element_type.temperature = temperature;
+    }
+    if let Some(heat_capacity) = definition.heat_capacity {
+        if heat_capacity == 0 {
+            return Err(invalid("heat_capacity", "must be at least 1"));
+        }
+        element_type.heat_capacity = heat_capacity;
+    }
+    if let Some(conductivity) = definition.conductivity {
+        if conductivity > MAX_CONDUCTIVITY {
+            return Err(invalid(
+                "conductivity",
+                &format!("must be at most {}", MAX_CONDUCTIVITY),
+            ));
+        }
+        element_type.conductivity = conductivity;
+    }
+    if let Some(ignition_temperature) = definition.ignition_temperature {
+        element_type.ignition_temperature = Some(ignition_temperature);
+    }
+    if let Some(above) = &definition.above {
+        element_type.above = Some(PhaseTransition {
+            temperature: above.temperature,
+            element: element("above", &above.element)?,
+            quenched: above
+                .quenched
+                .as_ref()
+                .map(|name| element("above", name))
+                .transpose()?,
+        });
+    }
+    if let Some(below) = &definition.below {
+        element_type.below = Some(PhaseTransition {
+            temperature: below.temperature,
+            element: element("below", &below.element)?,
+            quenched: below
+                .quenched
+                .as_ref()
+                .map(|name| element("below", name))
+                .transpose()?,
+        });
+    }
+    if let Some(selectable) = definition.selectable {
+        element_type.selectable = selectable;
+    }
+
+    // Liquids flow sideways by their strength, but cannot reach further than a chunk update can
+    if element_type.form == ElementForm::Liquid && element_type.strength as usize > CHUNK_REACH {
+        return Err(invalid(
+            "strength",
+            &format!("must be at most {} for a liquid", CHUNK_REACH),
+        ));
+    }
+
+    // Overlapping thresholds would make cells change back and forth every step
+    if let (Some(above), Some(below)) = (element_type.above, element_type.below) {
+        if above.temperature <= below.temperature {
+            return Err(invalid(
+                "above",
+                "must have a higher temperature than below",
+            ));
+        }
+    }
+    Ok(element_type)
+}
@@ -0,0 +1,188 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+};
+
+use crate::sandbox::*;
+
+// Saved sandbox layout (all numbers little endian):
+// - magic "FRSB"
+// - format version (u16)
+// - width and height (u32 each)
+// - runs of identical cells until width * height cells are covered, each consisting of
+//   run length (u16), element id (u8), variant (u8), strength (u8) and temperature (i16)
+// Version 1 files lack the temperature, their cells get the temperature of their element.
+const MAGIC: &[u8; 4] = b"FRSB";
+pub const FILE_FORMAT_VERSION: u16 = 2;
+// Guard against absurd sizes in corrupted files before allocating the cells
+pub(super) const MAX_DIMENSION: u32 = 8192;
+
+// Errors that can occur while saving or loading a sandbox
+#[derive(Debug)]
+pub enum SandBoxFileError {
+    Io(io::Error),
+    InvalidMagic,
+    UnsupportedVersion(u16),
+    InvalidSize { width: u32, height: u32 },
+    Truncated,
+    UnknownElement(u8),
+    InvalidRunLength,
+}
+
+impl fmt::Display for SandBoxFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SandBoxFileError::Io(error) => write!(f, "I/O error: {}", error),
+            SandBoxFileError::InvalidMagic => write!(f, "not a sandbox file"),
+            SandBoxFileError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported sandbox file version {} (expected at most {})",
+                version, FILE_FORMAT_VERSION
+            ),
+            SandBoxFileError::InvalidSize { width, height } => {
+                write!(f, "invalid sandbox size {}x{}", width, height)
+            }
+            SandBoxFileError::Truncated => write!(f, "sandbox file is truncated"),
+            SandBoxFileError::UnknownElement(id) => write!(f, "unknown element id {}", id),
+            SandBoxFileError::InvalidRunLength => {
+                write!(f, "cell data does not match the sandbox size")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SandBoxFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SandBoxFileError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SandBoxFileError {
+    fn from(error: io::Error) -> Self {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            SandBoxFileError::Truncated
+        } else {
+            SandBoxFileError::Io(error)
+        }
+    }
+}
+
+impl SandBox {
+    // Write the sandbox in the versioned binary file format
+    pub fn save_to<W: Write>(&self, writer: &mut W) -> Result<(), SandBoxFileError> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FILE_FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&(self.width() as u32).to_le_bytes())?;
+        writer.write_all(&(self.height() as u32).to_le_bytes())?;
+
+        let mut run: Option<(u16, (u8, u8, u8, i16))> = None;
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                let cell = self.get(x, y);
+                let data = (
+                    cell.element.id(),
+                    cell.variant,
+                    cell.strength,
+                    cell.temperature,
+                );
+                run = match run {
+                    Some((length, run_data)) if run_data == data && length < u16::MAX => {
+                        Some((length + 1, run_data))
+                    }
+                    Some((length, run_data)) => {
+                        write_run(writer, length, run_data)?;
+                        Some((1, data))
+                    }
+                    None => Some((1, data)),
+                };
+            }
+        }
+        if let Some((length, run_data)) = run {
+            write_run(writer, length, run_data)?;
+        }
+        Ok(())
+    }
+
+    // Read a sandbox written by save_to
+    pub fn load_from<R: Read>(reader: &mut R) -> Result<SandBox, SandBoxFileError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(SandBoxFileError::InvalidMagic);
+        }
+        let version = u16::from_le_bytes(read_bytes(reader)?);
+        if version == 0 || version > FILE_FORMAT_VERSION {
+            return Err(SandBoxFileError::UnsupportedVersion(version));
+        }
+        let width = u32::from_le_bytes(read_bytes(reader)?);
+        let height = u32::from_le_bytes(read_bytes(reader)?);
+        // At least one cell inside the indestructible border
+        if !(3..=MAX_DIMENSION).contains(&width) || !(3..=MAX_DIMENSION).contains(&height) {
+            return Err(SandBoxFileError::InvalidSize { width, height });
+        }
+
+        let mut sandbox = SandBox::empty(width as usize, height as usize);
+        let cell_count = sandbox.width() * sandbox.height();
+        let mut index = 0;
+        while index < cell_count {
+            let [l0, l1, element, variant, strength] = read_bytes(reader)?;
+            let length = u16::from_le_bytes([l0, l1]) as usize;
+            if length == 0 || index + length > cell_count {
+                return Err(SandBoxFileError::InvalidRunLength);
+            }
+            let element = Element::try_from(element).map_err(SandBoxFileError::UnknownElement)?;
+            let temperature = if version >= 2 {
+                i16::from_le_bytes(read_bytes(reader)?)
+            } else {
+                element_type(element).temperature
+            };
+            for i in index..index + length {
+                let (x, y) = (i % sandbox.width(), i / sandbox.width());
+                let cell = sandbox.get_mut(x, y);
+                cell.element = element;
+                cell.variant = variant;
+                cell.strength = strength;
+                cell.temperature = temperature;
+            }
+            index += length;
+        }
+
+        // The simulation relies on the border, so never trust the file for it
+        sandbox.add_border();
+        Ok(sandbox)
+    }
+
+    // Write the sandbox to a file
+    pub fn save_file(&self, path: &str) -> Result<(), SandBoxFileError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.save_to(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Read a sandbox from a file written by save_file
+    pub fn load_file(path: &str) -> Result<SandBox, SandBoxFileError> {
+        SandBox::load_from(&mut BufReader::new(File::open(path)?))
+    }
+}
+
+fn write_run<W: Write>(
+    writer: &mut W,
+    length: u16,
+    (element, variant, strength, temperature): (u8, u8, u8, i16),
+) -> Result<(), SandBoxFileError> {
+    writer.write_all(&length.to_le_bytes())?;
+    writer.write_all(&[element, variant, strength])?;
+    writer.write_all(&temperature.to_le_bytes())?;
+    Ok(())
+}
+
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], SandBoxFileError> {
+    let mut bytes = [0u8; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
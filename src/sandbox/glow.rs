@@ -0,0 +1,95 @@
+use crate::sandbox::*;
+
+// Number of cells the glow of a cell reaches in each direction
+pub const GLOW_RADIUS: usize = 4;
+// The summed glow of nearby cells is divided by this before it is added to the color of a cell.
+// The weights along an axis add up to (GLOW_RADIUS + 1)², so cells inside a large glowing area get
+// the full glow color added.
+const GLOW_DIVISOR: u32 = ((GLOW_RADIUS + 1) * (GLOW_RADIUS + 1)).pow(2) as u32;
+
+// Weight of the glow of a cell at a distance along one axis, falling off linearly
+const fn glow_weight(distance: usize) -> u32 {
+    (GLOW_RADIUS + 1 - distance) as u32
+}
+
+impl SandBox {
+    // Area of which the colors depend on the cells in an area, as it lies within the reach of
+    // their glow
+    pub fn glow_area(&self, area: DirtyRect) -> DirtyRect {
+        DirtyRect {
+            min_x: area.min_x.saturating_sub(GLOW_RADIUS),
+            min_y: area.min_y.saturating_sub(GLOW_RADIUS),
+            max_x: (area.max_x + GLOW_RADIUS).min(self.width() - 1),
+            max_y: (area.max_y + GLOW_RADIUS).min(self.height() - 1),
+        }
+    }
+
+    // Add the glow of nearby cells to RGBA pixel data with the dimensions of the sandbox, in an
+    // area that was just drawn. The glow of each glowing cell is spread horizontally and then
+    // vertically, so the time taken depends mostly on the number of glowing cells.
+    pub fn render_glow(&self, area: DirtyRect, pixels: &mut [u8]) {
+        // Cells whose glow reaches the area
+        let source = self.glow_area(area);
+        let width = area.width();
+
+        // Spread horizontally, for the columns of the area and the rows of the source
+        let mut spread = vec![[0u32; 3]; width * source.height()];
+        let mut any_glow = false;
+        for y in source.min_y..=source.max_y {
+            let row = (y - source.min_y) * width;
+            for x in source.min_x..=source.max_x {
+                let Some((r, g, b)) = self.get(x, y).glow() else {
+                    continue;
+                };
+                any_glow = true;
+                let from = x.saturating_sub(GLOW_RADIUS).max(area.min_x);
+                let to = (x + GLOW_RADIUS).min(area.max_x);
+                for target_x in from..=to {
+                    let weight = glow_weight(target_x.abs_diff(x));
+                    let sum = &mut spread[target_x - area.min_x + row];
+                    sum[0] += r as u32 * weight;
+                    sum[1] += g as u32 * weight;
+                    sum[2] += b as u32 * weight;
+                }
+            }
+        }
+        if !any_glow {
+            return;
+        }
+
+        // Spread vertically, for the cells of the area
+        let mut glows = vec![[0u32; 3]; width * area.height()];
+        for y in source.min_y..=source.max_y {
+            let from = y.saturating_sub(GLOW_RADIUS).max(area.min_y);
+            let to = (y + GLOW_RADIUS).min(area.max_y);
+            for column in 0..width {
+                let glow = spread[column + (y - source.min_y) * width];
+                if glow == [0; 3] {
+                    continue;
+                }
+                for target_y in from..=to {
+                    let weight = glow_weight(target_y.abs_diff(y));
+                    let sum = &mut glows[column + (target_y - area.min_y) * width];
+                    for channel in 0..3 {
+                        sum[channel] += glow[channel] * weight;
+                    }
+                }
+            }
+        }
+
+        // Add the glow to the pixels
+        for y in area.min_y..=area.max_y {
+            for column in 0..width {
+                let glow = glows[column + (y - area.min_y) * width];
+                if glow == [0; 3] {
+                    continue;
+                }
+                let pixel = (area.min_x + column + y * self.width()) * 4;
+                for channel in 0..3 {
+                    let value = pixels[pixel + channel] as u32 + glow[channel] / GLOW_DIVISOR;
+                    pixels[pixel + channel] = value.min(255) as u8;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use image::{codecs::png::PngEncoder, ColorType, ImageEncoder, ImageError, ImageResult, RgbaImage};
+
+use crate::sandbox::*;
+
+impl SandBox {
+    // Convert the sandbox to an image using the cell colors
+    pub fn to_image(&self) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width() as u32, self.height() as u32);
+        self.render_pixels(&mut image);
+        image
+    }
+
+    // Convert the sandbox to an image drawn with an overlay, the way it is shown on screen
+    pub fn to_overlay_image(&self, overlay: Overlay) -> RgbaImage {
+        let mut image = RgbaImage::new(self.width() as u32, self.height() as u32);
+        let area = DirtyRect {
+            min_x: 0,
+            min_y: 0,
+            max_x: self.width() - 1,
+            max_y: self.height() - 1,
+        };
+        self.render_area(area, Some(overlay), &mut image);
+        image
+    }
+
+    // Write the colors of all cells to RGBA pixel data with the dimensions of the sandbox
+    pub fn render_pixels(&self, pixels: &mut [u8]) {
+        let rows = self.cells.chunks_exact(self.width());
+        let pixel_rows = pixels.chunks_exact_mut(self.width() * 4);
+        for (y, (row, pixel_row)) in rows.zip(pixel_rows).enumerate() {
+            for (x, (cell, pixel)) in row.iter().zip(pixel_row.chunks_exact_mut(4)).enumerate() {
+                let (r, g, b) = cell.shaded_color(x, y);
+                pixel.copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+    }
+
+    // Write the colors of the cells in an area to RGBA pixel data with the dimensions of the
+    // sandbox, leaving the other pixels alone. Cells are drawn with an overlay instead of their
+    // own colors when one is given.
+    pub fn render_area(&self, area: DirtyRect, overlay: Option<Overlay>, pixels: &mut [u8]) {
+        for y in area.min_y..=area.max_y {
+            let row = y * self.width();
+            let cells = &self.cells[row + area.min_x..=row + area.max_x];
+            let pixel_row = &mut pixels[(row + area.min_x) * 4..(row + area.max_x + 1) * 4];
+            for (x, (cell, pixel)) in cells.iter().zip(pixel_row.chunks_exact_mut(4)).enumerate() {
+                let x = area.min_x + x;
+                let (r, g, b) = match overlay {
+                    Some(overlay) => self.overlay_color(x, y, overlay),
+                    None => cell.shaded_color(x, y),
+                };
+                pixel.copy_from_slice(&[r, g, b, 255]);
+            }
+        }
+    }
+
+    // Write the sandbox as a PNG, upscaled by an integer factor
+    pub fn export_png<W: Write>(&self, writer: &mut W, scale: u32) -> ImageResult<()> {
+        write_png(writer, &self.to_image(), scale)
+    }
+
+    // Write the sandbox to a PNG file, upscaled by an integer factor
+    pub fn export_png_file(&self, path: &str, scale: u32) -> ImageResult<()> {
+        write_png_file(path, &self.to_image(), scale)
+    }
+}
+
+// Write an image as a PNG, upscaled by an integer factor using nearest-neighbour sampling
+pub fn write_png<W: Write>(writer: &mut W, image: &RgbaImage, scale: u32) -> ImageResult<()> {
+    let scale = scale.max(1);
+    let scaled = if scale == 1 {
+        image.clone()
+    } else {
+        RgbaImage::from_fn(image.width() * scale, image.height() * scale, |x, y| {
+            *image.get_pixel(x / scale, y / scale)
+        })
+    };
+    PngEncoder::new(writer).write_image(
+        scaled.as_raw(),
+        scaled.width(),
+        scaled.height(),
+        ColorType::Rgba8,
+    )
+}
+
+// Write an image to a PNG file, upscaled by an integer factor
+pub fn write_png_file(path: &str, image: &RgbaImage, scale: u32) -> ImageResult<()> {
+    let mut writer = BufWriter::new(File::create(path).map_err(ImageError::IoError)?);
+    write_png(&mut writer, image, scale)?;
+    writer.flush().map_err(ImageError::IoError)
+}
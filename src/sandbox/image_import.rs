@@ -0,0 +1,161 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader, Cursor, Read},
+};
+
+use image::{io::Reader as ImageReader, ImageFormat, RgbaImage};
+
+use crate::sandbox::*;
+
+// Pixels with less opacity than this are imported as air
+const MIN_OPAQUE_ALPHA: u8 = 128;
+
+// Mapping from pixel colors to elements, used when importing images. Each pixel is turned into the
+// element with the nearest color, so anti-aliased or slightly off colors still map sensibly.
+#[derive(Clone, Debug)]
+pub struct ElementPalette {
+    entries: Vec<((u8, u8, u8), Element)>,
+}
+
+impl ElementPalette {
+    // Palette using an explicit color table
+    pub fn new(entries: Vec<((u8, u8, u8), Element)>) -> Self {
+        Self { entries }
+    }
+
+    // Palette using the colors of the elements that can be selected in the interface and air,
+    // including the colors of their palettes so exported images import as the same elements
+    pub fn elements() -> Self {
+        let entries = all_elements()
+            .filter(|element| *element == Element::Air || element_type(*element).selectable)
+            .flat_map(|element| {
+                let element_type = element_type(element);
+                std::iter::once(element_type.color)
+                    .chain(element_type.palette.iter().copied())
+                    .map(move |color| (color, element))
+            })
+            .collect();
+        Self { entries }
+    }
+
+    // Find the element with the color closest to the given color
+    pub fn nearest(&self, color: (u8, u8, u8)) -> Element {
+        self.entries
+            .iter()
+            .min_by_key(|(entry_color, _)| color_distance_sq(*entry_color, color))
+            .map(|(_, element)| *element)
+            .unwrap_or(Element::Air)
+    }
+}
+
+impl Default for ElementPalette {
+    fn default() -> Self {
+        ElementPalette::elements()
+    }
+}
+
+// Errors that can occur while importing an image as a sandbox
+#[derive(Debug)]
+pub enum ImageImportError {
+    Io(io::Error),
+    Image(image::ImageError),
+    InvalidSize { width: u32, height: u32 },
+}
+
+impl fmt::Display for ImageImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageImportError::Io(error) => write!(f, "I/O error: {}", error),
+            ImageImportError::Image(error) => write!(f, "invalid image: {}", error),
+            ImageImportError::InvalidSize { width, height } => {
+                write!(f, "invalid sandbox size {}x{}", width, height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageImportError::Io(error) => Some(error),
+            ImageImportError::Image(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageImportError {
+    fn from(error: io::Error) -> Self {
+        ImageImportError::Io(error)
+    }
+}
+
+impl From<image::ImageError> for ImageImportError {
+    fn from(error: image::ImageError) -> Self {
+        ImageImportError::Image(error)
+    }
+}
+
+impl SandBox {
+    // Create a sandbox from PNG data, with the dimensions of the image
+    pub fn import_png<R: Read>(
+        reader: &mut R,
+        palette: &ElementPalette,
+    ) -> Result<SandBox, ImageImportError> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        // Check the size before decoding, to not allocate the pixels of absurdly large images
+        let (width, height) =
+            ImageReader::with_format(Cursor::new(&data), ImageFormat::Png).into_dimensions()?;
+        check_size(width, height)?;
+        let image = image::load_from_memory_with_format(&data, ImageFormat::Png)?;
+        SandBox::from_image(&image.to_rgba8(), palette)
+    }
+
+    // Create a sandbox from a PNG file
+    pub fn import_png_file(
+        path: &str,
+        palette: &ElementPalette,
+    ) -> Result<SandBox, ImageImportError> {
+        SandBox::import_png(&mut BufReader::new(File::open(path)?), palette)
+    }
+
+    // Create a sandbox from an image, mapping each pixel to an element using the palette
+    pub fn from_image(
+        image: &RgbaImage,
+        palette: &ElementPalette,
+    ) -> Result<SandBox, ImageImportError> {
+        let (width, height) = image.dimensions();
+        check_size(width, height)?;
+        let mut sandbox = SandBox::empty(width as usize, height as usize);
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let [r, g, b, a] = pixel.0;
+            let element = if a < MIN_OPAQUE_ALPHA {
+                Element::Air
+            } else {
+                palette.nearest((r, g, b))
+            };
+            sandbox.set_element(x as usize, y as usize, element);
+        }
+        sandbox.add_border();
+        Ok(sandbox)
+    }
+}
+
+// The same sizes as loading a saved sandbox allows, with at least one cell inside the
+// indestructible border
+fn check_size(width: u32, height: u32) -> Result<(), ImageImportError> {
+    if (3..=MAX_DIMENSION).contains(&width) && (3..=MAX_DIMENSION).contains(&height) {
+        Ok(())
+    } else {
+        Err(ImageImportError::InvalidSize { width, height })
+    }
+}
+
+fn color_distance_sq(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let dr = a.0 as i32 - b.0 as i32;
+    let dg = a.1 as i32 - b.1 as i32;
+    let db = a.2 as i32 - b.2 as i32;
+    (dr * dr + dg * dg + db * db) as u32
+}
@@ -1,41 +1,84 @@
+#[cfg(feature = "bevy")]
 use bevy::{
     prelude::*,
     render::render_resource::{Extent3d, TextureDimension, TextureFormat},
 };
 
 mod cell;
+mod chunk;
 mod element;
+mod element_file;
+mod file_format;
+mod glow;
+mod image_export;
+mod image_import;
+mod overlay;
+mod reaction;
+#[cfg(feature = "scripting")]
+mod script;
+mod view;
+mod visited;
 
 pub use cell::*;
+pub use chunk::*;
 pub use element::*;
+pub use element_file::*;
+pub use file_format::*;
+pub use glow::*;
+pub use image_export::*;
+pub use image_import::*;
+pub use overlay::*;
+pub use reaction::*;
+#[cfg(feature = "scripting")]
+pub use script::*;
+pub use view::*;
+use visited::*;
+
+#[cfg(not(target_family = "wasm"))]
+use rayon::prelude::*;
 
 // The sandbox consisting of a grid of cells with elements that is simulated
-#[derive(Component)]
+#[derive(Clone)]
+#[cfg_attr(feature = "bevy", derive(Component))]
 pub struct SandBox {
     width: usize,
     height: usize,
     cells: Vec<Cell>,
+    visited: VisitedCells,
+    chunks: Vec<Chunk>,
+    chunks_x: usize,
+    chunks_y: usize,
     visited_state: bool,
     pub render_time_ms: u128,
+    // Number of cells redrawn by the last render
+    pub rendered_cells: usize,
+    // Estimated time saved per render by redrawing only the changed cells, averaged over recent
+    // renders
+    pub render_time_saved_ms: f64,
 }
 
 impl SandBox {
     pub fn new(width: usize, height: usize) -> Self {
         let mut sandbox = SandBox::empty(width, height);
-        // Set indestructible pixels at the border to ease computations
-        for x in 0..sandbox.width() {
-            sandbox.set_element(x, 0, Element::Indestructible);
-            sandbox.set_element(x, sandbox.height() - 1, Element::Indestructible);
+        sandbox.add_border();
+        sandbox
+    }
+
+    // Set indestructible pixels at the border to ease computations
+    fn add_border(&mut self) {
+        for x in 0..self.width() {
+            self.set_element(x, 0, Element::Indestructible);
+            self.set_element(x, self.height() - 1, Element::Indestructible);
         }
-        for y in 0..sandbox.height() {
-            sandbox.set_element(0, y, Element::Indestructible);
-            sandbox.set_element(sandbox.width() - 1, y, Element::Indestructible);
+        for y in 0..self.height() {
+            self.set_element(0, y, Element::Indestructible);
+            self.set_element(self.width() - 1, y, Element::Indestructible);
         }
-        sandbox
     }
 
     fn empty(width: usize, height: usize) -> Self {
-        SandBox {
+        let (chunks_x, chunks_y) = (width.div_ceil(CHUNK_SIZE), height.div_ceil(CHUNK_SIZE));
+        let mut sandbox = SandBox {
             width,
             height,
             cells: vec![
@@ -43,13 +86,22 @@ impl SandBox {
                     element: Element::Air,
                     variant: 0,
                     strength: 0,
-                    visited: false,
+                    temperature: AMBIENT_TEMPERATURE,
                 };
                 width * height
             ],
+            visited: VisitedCells::new(width, height),
+            chunks: vec![Chunk::default(); chunks_x * chunks_y],
+            chunks_x,
+            chunks_y,
             visited_state: false,
             render_time_ms: 0,
-        }
+            rendered_cells: 0,
+            render_time_saved_ms: 0.0,
+        };
+        // A new sandbox was never drawn
+        sandbox.mark_all_dirty();
+        sandbox
     }
 
     pub fn get(&self, x: usize, y: usize) -> &Cell {
@@ -57,20 +109,30 @@ impl SandBox {
         &self.cells[index]
     }
 
+    // Mutable access to a cell, which is assumed to change
     pub fn get_mut(&mut self, x: usize, y: usize) -> &mut Cell {
+        self.mark_changed(x, y);
         let index = self.index(x, y);
         &mut self.cells[index]
     }
 
+    // Change the temperature of a cell. Temperatures are not drawn, and a change of a single degree
+    // can be the random rounding of heat flow between nearly even temperatures, which never
+    // settles. Only larger changes keep the chunk awake, so such chunks can fall asleep.
+    pub fn set_temperature(&mut self, x: usize, y: usize, temperature: i16) {
+        let index = self.index(x, y);
+        if self.cells[index].temperature.abs_diff(temperature) > 1 {
+            self.keep_awake(x, y);
+        }
+        self.cells[index].temperature = temperature;
+    }
+
     pub fn reduce_strength(&mut self, x: usize, y: usize, amount: u8) -> bool {
         let index = self.index(x, y);
-        let cell = &mut self.cells[index];
-        if cell.strength > 0 {
-            cell.strength = if cell.strength > amount {
-                cell.strength - amount
-            } else {
-                0
-            };
+        if self.cells[index].strength > 0 {
+            self.mark_changed(x, y);
+            let cell = &mut self.cells[index];
+            cell.strength = cell.strength.saturating_sub(amount);
             true
         } else {
             false
@@ -89,14 +151,29 @@ impl SandBox {
         strength: u8,
     ) {
         let index = self.index(x, y);
-        let cell = &mut self.cells[index];
-        if cell.element == Element::Indestructible {
+        if self.cells[index].element == Element::Indestructible {
             // Cannot edit these blocks
             return;
         }
+        self.mark_changed(x, y);
+        let cell = &mut self.cells[index];
         cell.element = element;
-        cell.visited = self.visited_state;
+        cell.variant = 0;
         cell.strength = strength;
+        cell.temperature = element_type(element).temperature;
+        self.visited.set(x, y, self.visited_state);
+    }
+
+    // Set all data of a cell, e.g. to restore a cell to an earlier state
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        let index = self.index(x, y);
+        if self.cells[index].element == Element::Indestructible {
+            // Cannot edit these blocks
+            return;
+        }
+        self.mark_changed(x, y);
+        self.cells[index] = cell;
+        self.visited.set(x, y, self.visited_state);
     }
 
     pub fn set_element(&mut self, x: usize, y: usize, element: Element) {
@@ -106,22 +183,26 @@ impl SandBox {
     pub fn swap(&mut self, x: usize, y: usize, x2: usize, y2: usize) {
         let index1 = self.index(x, y);
         let index2 = self.index(x2, y2);
-        let mut cell = self.cells[index1].clone();
-        let mut cell2 = self.cells[index2].clone();
-        if cell.element == Element::Indestructible || cell2.element == Element::Indestructible {
+        if self.cells[index1].element == Element::Indestructible
+            || self.cells[index2].element == Element::Indestructible
+        {
             // Cannot edit these blocks
             return;
         }
-        // cell is moved to the place of cell 2, so becomes the second cell
-        cell.visited = self.visited_state;
-        cell2.visited = self.visited_state;
-        self.cells[index1] = cell2;
-        self.cells[index2] = cell;
+        self.mark_changed(x, y);
+        self.mark_changed(x2, y2);
+        self.cells.swap(index1, index2);
+        self.visited.set(x, y, self.visited_state);
+        self.visited.set(x2, y2, self.visited_state);
+    }
+
+    // Whether a cell was already updated during the current step
+    pub fn is_visited(&self, x: usize, y: usize) -> bool {
+        self.visited.get(x, y) == self.visited_state
     }
 
     pub fn set_visited(&mut self, x: usize, y: usize) {
-        let index = self.index(x, y);
-        self.cells[index].visited = self.visited_state;
+        self.visited.set(x, y, self.visited_state);
     }
 
     pub fn width(&self) -> usize {
@@ -141,18 +222,174 @@ impl SandBox {
         self.visited_state
     }
 
+    // Set the visited state and mark all cells as visited in it, as is the case between steps
+    pub fn reset_visited_state(&mut self, visited_state: bool) {
+        self.visited_state = visited_state;
+        self.visited
+            .fill(0..self.width, 0..self.height, visited_state);
+    }
+
+    // Number of chunks horizontally and vertically
+    pub fn chunk_count(&self) -> (usize, usize) {
+        (self.chunks_x, self.chunks_y)
+    }
+
+    pub fn chunk(&self, chunk_x: usize, chunk_y: usize) -> &Chunk {
+        &self.chunks[chunk_x + chunk_y * self.chunks_x]
+    }
+
+    pub fn awake_chunk_count(&self) -> usize {
+        self.chunks.iter().filter(|chunk| chunk.is_awake()).count()
+    }
+
+    // Whether the chunk containing a cell is simulated
+    pub fn is_awake(&self, x: usize, y: usize) -> bool {
+        self.chunk(x / CHUNK_SIZE, y / CHUNK_SIZE).is_awake()
+    }
+
+    // Keep the chunk of a cell (and of its neighbors across a chunk edge) awake, for cells that
+    // may still change without anything around them changing
+    pub fn keep_awake(&mut self, x: usize, y: usize) {
+        for (chunk_x, chunk_y) in chunks_around(x, y, self.chunks_x, self.chunks_y) {
+            self.wake_chunk(chunk_x, chunk_y);
+        }
+    }
+
+    // Wake all chunks, as is the case for a new or loaded sandbox
+    pub fn wake_all(&mut self) {
+        for chunk_y in 0..self.chunks_y {
+            for chunk_x in 0..self.chunks_x {
+                self.wake_chunk(chunk_x, chunk_y);
+            }
+        }
+    }
+
+    // Let chunks without changes during the last step move closer to falling asleep
+    pub fn end_chunk_step(&mut self) {
+        for chunk in self.chunks.iter_mut() {
+            chunk.end_step();
+        }
+    }
+
+    // Update the chunks of one phase of a checkerboard pattern, e.g. phase (1, 0) updates the
+    // chunks with an odd x and even y. Chunks of a phase are two chunks apart, so they can be
+    // updated in parallel without affecting each other and the outcome does not depend on the
+    // order. Chunks for which skip returns true are left alone.
+    pub fn update_chunks<U, S>(&mut self, phase: (usize, usize), skip: S, update: U)
+    where
+        U: Fn(&mut SandBoxView, usize, usize) + Sync,
+        S: Fn(usize, usize) -> bool,
+    {
+        let chunks: Vec<(usize, usize)> = (phase.1..self.chunks_y)
+            .step_by(2)
+            .flat_map(|chunk_y| {
+                (phase.0..self.chunks_x)
+                    .step_by(2)
+                    .map(move |chunk_x| (chunk_x, chunk_y))
+            })
+            .filter(|&(chunk_x, chunk_y)| !skip(chunk_x, chunk_y))
+            .collect();
+
+        let cells = SharedCells {
+            cells: self.cells.as_mut_ptr(),
+            visited: self.visited.as_mut_ptr(),
+            visited_stride: self.visited.stride(),
+            width: self.width,
+            height: self.height,
+            chunks_x: self.chunks_x,
+            chunks_y: self.chunks_y,
+            visited_state: self.visited_state,
+        };
+        let update_chunk = |&(chunk_x, chunk_y): &(usize, usize)| {
+            // Safety: views of chunks two chunks apart do not overlap
+            let mut view = unsafe { SandBoxView::new(&cells, chunk_x, chunk_y) };
+            update(&mut view, chunk_x, chunk_y);
+            view.into_changes()
+        };
+        #[cfg(not(target_family = "wasm"))]
+        let changes: Vec<ViewChanges> = chunks.par_iter().map(update_chunk).collect();
+        #[cfg(target_family = "wasm")]
+        let changes: Vec<ViewChanges> = chunks.iter().map(update_chunk).collect();
+
+        for changes in changes {
+            for index in 0..9 {
+                let (chunk_x, chunk_y) = changes.neighbor(index);
+                if let Some(dirty) = changes.dirty[index] {
+                    self.chunks[chunk_x + chunk_y * self.chunks_x].mark_dirty_rect(dirty);
+                }
+                if changes.woken[index] {
+                    self.wake_chunk(chunk_x, chunk_y);
+                }
+            }
+        }
+    }
+
+    // Changed areas of all chunks since the dirty rectangles were last cleared
+    pub fn dirty_rects(&self) -> impl Iterator<Item = DirtyRect> + '_ {
+        self.chunks.iter().filter_map(|chunk| chunk.dirty_rect())
+    }
+
+    // Mark all cells as changed, so everything is drawn again
+    pub fn mark_all_dirty(&mut self) {
+        for chunk_y in 0..self.chunks_y {
+            for chunk_x in 0..self.chunks_x {
+                self.chunks[chunk_x + chunk_y * self.chunks_x].mark_dirty_rect(DirtyRect {
+                    min_x: chunk_x * CHUNK_SIZE,
+                    min_y: chunk_y * CHUNK_SIZE,
+                    max_x: ((chunk_x + 1) * CHUNK_SIZE).min(self.width) - 1,
+                    max_y: ((chunk_y + 1) * CHUNK_SIZE).min(self.height) - 1,
+                });
+            }
+        }
+    }
+
+    pub fn clear_dirty_rects(&mut self) {
+        for chunk in self.chunks.iter_mut() {
+            chunk.clear_dirty();
+        }
+    }
+
+    fn mark_changed(&mut self, x: usize, y: usize) {
+        self.chunks[x / CHUNK_SIZE + y / CHUNK_SIZE * self.chunks_x].mark_dirty(x, y);
+        self.keep_awake(x, y);
+    }
+
+    fn wake_chunk(&mut self, chunk_x: usize, chunk_y: usize) {
+        if self.chunks[chunk_x + chunk_y * self.chunks_x].wake() {
+            // Cells of sleeping chunks were not visited, so mark them as visited in this step
+            // like all other cells
+            self.visited.fill(
+                chunk_x * CHUNK_SIZE..((chunk_x + 1) * CHUNK_SIZE).min(self.width),
+                chunk_y * CHUNK_SIZE..((chunk_y + 1) * CHUNK_SIZE).min(self.height),
+                self.visited_state,
+            );
+        }
+    }
+
     #[inline(always)]
     fn index(&self, x: usize, y: usize) -> usize {
         x + y * self.width
     }
 }
 
-pub fn spawn_sandbox(mut commands: Commands, images: &mut Assets<Image>, width: u32, height: u32) {
+// Replace the sandbox in place, resizing its texture to match
+#[cfg(feature = "bevy")]
+pub fn replace_sandbox(sandbox: &mut SandBox, image: &mut Image, new_sandbox: SandBox) {
+    image.resize(Extent3d {
+        width: new_sandbox.width() as u32,
+        height: new_sandbox.height() as u32,
+        depth_or_array_layers: 1,
+    });
+    *sandbox = new_sandbox;
+}
+
+#[cfg(feature = "bevy")]
+pub fn spawn_sandbox(mut commands: Commands, images: &mut Assets<Image>, sandbox: SandBox) {
     let image_handle = {
         let image = Image::new_fill(
             Extent3d {
-                width,
-                height,
+                width: sandbox.width() as u32,
+                height: sandbox.height() as u32,
                 depth_or_array_layers: 1,
             },
             TextureDimension::D2,
@@ -162,7 +399,7 @@ pub fn spawn_sandbox(mut commands: Commands, images: &mut Assets<Image>, width:
         images.add(image)
     };
     commands
-        .spawn(SandBox::new(width as usize, height as usize))
+        .spawn(sandbox)
         .insert(SpriteBundle {
             texture: image_handle,
             transform: Transform {
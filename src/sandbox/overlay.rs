@@ -0,0 +1,116 @@
+use std::fmt;
+
+use crate::sandbox::*;
+
+// Debug views showing state of the cells that is not visible in their normal colors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overlay {
+    // Heat map of the strength of each cell
+    Strength,
+    // A color for each value of the variant of a cell, such as the growth depth of plants
+    Variant,
+    // Cells visited by the last step in white, cells that were skipped in black
+    Visited,
+    // Awake chunks tinted green, sleeping chunks darkened
+    ChunkActivity,
+    // A color for each element form
+    Form,
+}
+
+pub const OVERLAYS: [Overlay; 5] = [
+    Overlay::Strength,
+    Overlay::Variant,
+    Overlay::Visited,
+    Overlay::ChunkActivity,
+    Overlay::Form,
+];
+
+const AWAKE_TINT: (u8, u8, u8) = (0, 255, 0);
+const VISITED_COLOR: (u8, u8, u8) = (255, 255, 255);
+const NOT_VISITED_COLOR: (u8, u8, u8) = (0, 0, 0);
+
+impl Overlay {
+    // Whether the overlay shows state that changes without the cells being marked as changed, so
+    // the whole sandbox has to be drawn again every frame
+    pub fn redraws_all(&self) -> bool {
+        matches!(self, Overlay::Visited | Overlay::ChunkActivity)
+    }
+}
+
+impl fmt::Display for Overlay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Overlay::Strength => "Strength",
+            Overlay::Variant => "Variant",
+            Overlay::Visited => "Visited",
+            Overlay::ChunkActivity => "Chunk activity",
+            Overlay::Form => "Element form",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl SandBox {
+    // Color of a cell as drawn with an overlay
+    pub fn overlay_color(&self, x: usize, y: usize, overlay: Overlay) -> (u8, u8, u8) {
+        let cell = self.get(x, y);
+        match overlay {
+            Overlay::Strength => heat_color(cell.strength),
+            Overlay::Variant => value_color(cell.variant),
+            Overlay::Visited => {
+                if self.is_visited(x, y) {
+                    VISITED_COLOR
+                } else {
+                    NOT_VISITED_COLOR
+                }
+            }
+            Overlay::ChunkActivity => {
+                let color = cell.shaded_color(x, y);
+                if self.is_awake(x, y) {
+                    mix(color, AWAKE_TINT, 1, 2)
+                } else {
+                    mix(color, (0, 0, 0), 2, 3)
+                }
+            }
+            Overlay::Form => {
+                if cell.element == Element::Air {
+                    return (0, 0, 0);
+                }
+                match element_type(cell.element).form {
+                    ElementForm::Solid => (128, 128, 128),
+                    ElementForm::Powder => (230, 200, 60),
+                    ElementForm::Liquid => (40, 100, 240),
+                    ElementForm::Gas => (230, 130, 230),
+                }
+            }
+        }
+    }
+}
+
+// Black through red and yellow to white
+fn heat_color(value: u8) -> (u8, u8, u8) {
+    let value = value as u32 * 3;
+    (
+        value.min(255) as u8,
+        value.saturating_sub(255).min(255) as u8,
+        value.saturating_sub(510) as u8,
+    )
+}
+
+// Black for zero, and colors of clearly different hues for small values
+fn value_color(value: u8) -> (u8, u8, u8) {
+    if value == 0 {
+        return (0, 0, 0);
+    }
+    // Steps around the color wheel by a bit more than a third, so neighboring values differ
+    let hue = (value as u32 * 137) % 360;
+    let rise = (hue % 60 * 255 / 60) as u8;
+    match hue / 60 {
+        0 => (255, rise, 0),
+        1 => (255 - rise, 255, 0),
+        2 => (0, 255, rise),
+        3 => (0, 255 - rise, 255),
+        4 => (rise, 0, 255),
+        _ => (255, 0, 255 - rise),
+    }
+}
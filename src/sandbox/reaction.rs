@@ -0,0 +1,112 @@
+use std::sync::OnceLock;
+
+use crate::sandbox::*;
+
+// The cells a reaction applies to, either a specific element or any element with a flag
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reactant {
+    Element(Element),
+    Flag(u32),
+}
+
+impl Reactant {
+    pub fn matches(&self, element: Element) -> bool {
+        match self {
+            Reactant::Element(reactant) => *reactant == element,
+            Reactant::Flag(flag) => element_type(element).has_flag(*flag),
+        }
+    }
+}
+
+// A cell of one reactant touching a cell of another reactant, which react with a chance of 1 in
+// chance per step. Each reaction uses up one strength of the cells that have a product, which
+// they turn into when out of strength. So strong materials take longer to react, and e.g. acid
+// is used up by dissolving things. With a strength divisor the chance is at least the strength of
+// the touching cell divided by it, so strong cells also react less often.
+#[derive(Clone, Copy, Debug)]
+pub struct Reaction {
+    pub reactant: Reactant,
+    pub touching: Reactant,
+    pub chance: u32,
+    pub strength_divisor: Option<u32>,
+    pub product: Option<Element>,
+    pub touching_product: Option<Element>,
+}
+
+impl Reaction {
+    // Chance of the reaction with a touching cell of a strength, 1 in the returned value per step
+    #[inline(always)]
+    pub fn chance_with(&self, touching_strength: u8) -> u32 {
+        match self.strength_divisor {
+            Some(divisor) => self.chance.max(touching_strength as u32 / divisor),
+            None => self.chance,
+        }
+    }
+}
+
+pub static BUILTIN_REACTIONS: [Reaction; 3] = [
+    // Water dilutes acid
+    Reaction {
+        reactant: Reactant::Element(Element::Water),
+        touching: Reactant::Element(Element::Acid),
+        chance: 4,
+        strength_divisor: None,
+        product: None,
+        touching_product: Some(Element::Water),
+    },
+    // Acid dissolves things, turning into smoke in the process
+    Reaction {
+        reactant: Reactant::Flag(FLAG_ACIDIC),
+        touching: Reactant::Flag(FLAG_DISSOLVES_IN_ACID),
+        chance: 2,
+        strength_divisor: Some(2),
+        product: Some(Element::Smoke),
+        touching_product: Some(Element::Air),
+    },
+    // Iron rusts when wet
+    Reaction {
+        reactant: Reactant::Element(Element::Iron),
+        touching: Reactant::Flag(FLAG_CAUSES_RUST),
+        chance: 3,
+        strength_divisor: None,
+        product: Some(Element::Rust),
+        touching_product: None,
+    },
+];
+
+static REACTIONS: OnceLock<Vec<Reaction>> = OnceLock::new();
+static ELEMENT_REACTIONS: OnceLock<Vec<Vec<Reaction>>> = OnceLock::new();
+
+// All reactions, the built-in ones unless others were installed at startup
+pub fn reactions() -> &'static [Reaction] {
+    REACTIONS.get_or_init(|| BUILTIN_REACTIONS.to_vec())
+}
+
+// Reactions of an element with its neighbors, in table order
+#[inline(always)]
+pub fn element_reactions(element: Element) -> &'static [Reaction] {
+    let element_reactions = ELEMENT_REACTIONS.get_or_init(|| {
+        all_elements()
+            .map(|element| {
+                reactions()
+                    .iter()
+                    .filter(|reaction| reaction.reactant.matches(element))
+                    .copied()
+                    .collect()
+            })
+            .collect()
+    });
+    &element_reactions[element.id() as usize]
+}
+
+// Replace the reactions, e.g. with those loaded from a file. Like the element definitions this
+// must happen before they are first used. Returns the reactions when that is not the case.
+pub fn install_reactions(reactions: Vec<Reaction>) -> Result<(), Vec<Reaction>> {
+    REACTIONS.set(reactions)
+}
+
+// Whether reactions were installed or the built-in ones are in use, so no others can be installed
+// anymore
+pub fn reactions_installed() -> bool {
+    REACTIONS.get().is_some()
+}
@@ -0,0 +1,287 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+use rhai::{Engine, EvalAltResult, ImmutableString, Scope, AST, INT};
+
+use crate::pseudo_random::PseudoRandom;
+use crate::sandbox::*;
+
+// Number of operations a script may use for a single cell when its element does not set a budget
+pub const DEFAULT_SCRIPT_BUDGET: u64 = 1000;
+// Number of operations a script may use for all cells of a chunk together in a single step when
+// its element does not set a step budget
+pub const DEFAULT_SCRIPT_STEP_BUDGET: u64 = 100_000;
+
+thread_local! {
+    // Number of operations used so far by the script running on this thread
+    static OPERATIONS: std::cell::Cell<u64> = const { std::cell::Cell::new(0) };
+}
+
+// Behaviour of an element written in Rhai. The script runs for every cell of the element each
+// step, with a variable cells giving access to the cell (0, 0) and its direct neighbors:
+//
+//     if cells.element(0, 1) == "Water" && cells.chance(10) {
+//         cells.set_element(0, 0, "Air");
+//     }
+//
+// Offsets range from -1 to 1, with y pointing down. Available functions are element, form,
+// strength, variant and temperature to read a cell, set_element, set_strength, set_variant and
+// set_temperature to change it, swap(dx, dy, dx2, dy2) and chance(n), which is true once per n
+// calls. Indestructible cells cannot be changed or created. A script that runs out of its budget of
+// operations or causes an error is stopped at the end of the step, after which the element
+// behaves as if it had no script. Once the cells of an element in a chunk used up the step budget
+// of its script, the script is skipped for the rest of that chunk in the step. Each chunk has its
+// own budget, so which cells miss out does not depend on the order the chunks are updated in.
+pub struct ElementScript {
+    engine: Engine,
+    ast: AST,
+    stopped: AtomicBool,
+    step_budget: u64,
+}
+
+// Operations used by the scripts of each element while updating a single chunk
+#[derive(Default)]
+pub struct ScriptUsage {
+    operations: Vec<u64>,
+}
+
+impl ScriptUsage {
+    fn operations(&mut self, element: Element) -> &mut u64 {
+        let index = element.id() as usize;
+        if self.operations.len() <= index {
+            self.operations.resize(index + 1, 0);
+        }
+        &mut self.operations[index]
+    }
+}
+
+// Error raised in a script
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+// The cells a script can access, copied from the sandbox and written back when changed
+#[derive(Clone)]
+struct Neighborhood {
+    cells: [Cell; 9],
+    random: PseudoRandom,
+}
+
+impl Neighborhood {
+    fn index(dx: INT, dy: INT) -> ScriptResult<usize> {
+        if (-1..=1).contains(&dx) && (-1..=1).contains(&dy) {
+            Ok((dx + 1 + (dy + 1) * 3) as usize)
+        } else {
+            Err(format!("offset ({}, {}) is outside of the neighborhood", dx, dy).into())
+        }
+    }
+
+    fn get(&self, dx: INT, dy: INT) -> ScriptResult<&Cell> {
+        Ok(&self.cells[Neighborhood::index(dx, dy)?])
+    }
+
+    // Cell to change, or None for indestructible cells
+    fn get_mut(&mut self, dx: INT, dy: INT) -> ScriptResult<Option<&mut Cell>> {
+        let cell = &mut self.cells[Neighborhood::index(dx, dy)?];
+        Ok((cell.element != Element::Indestructible).then_some(cell))
+    }
+
+    fn element(&mut self, dx: INT, dy: INT) -> ScriptResult<ImmutableString> {
+        Ok(element_type(self.get(dx, dy)?.element).name.into())
+    }
+
+    fn form(&mut self, dx: INT, dy: INT) -> ScriptResult<ImmutableString> {
+        let form = match element_type(self.get(dx, dy)?.element).form {
+            ElementForm::Solid => "Solid",
+            ElementForm::Powder => "Powder",
+            ElementForm::Liquid => "Liquid",
+            ElementForm::Gas => "Gas",
+        };
+        Ok(form.into())
+    }
+
+    fn strength(&mut self, dx: INT, dy: INT) -> ScriptResult<INT> {
+        Ok(self.get(dx, dy)?.strength as INT)
+    }
+
+    fn variant(&mut self, dx: INT, dy: INT) -> ScriptResult<INT> {
+        Ok(self.get(dx, dy)?.variant as INT)
+    }
+
+    fn temperature(&mut self, dx: INT, dy: INT) -> ScriptResult<INT> {
+        Ok(self.get(dx, dy)?.temperature as INT)
+    }
+
+    fn set_element(&mut self, dx: INT, dy: INT, name: &str) -> ScriptResult<()> {
+        let element = all_elements()
+            .find(|element| element_type(*element).name == name)
+            .ok_or_else(|| format!("unknown element \"{}\"", name))?;
+        // Like existing indestructible cells, new ones cannot be created
+        let cell = self.get_mut(dx, dy)?;
+        if let Some(cell) = cell.filter(|_| element != Element::Indestructible) {
+            let element_type = element_type(element);
+            cell.element = element;
+            cell.variant = 0;
+            cell.strength = element_type.strength;
+            cell.temperature = element_type.temperature;
+        }
+        Ok(())
+    }
+
+    fn set_strength(&mut self, dx: INT, dy: INT, strength: INT) -> ScriptResult<()> {
+        let strength = u8::try_from(strength)
+            .map_err(|_| format!("strength {} is not between 0 and 255", strength))?;
+        if let Some(cell) = self.get_mut(dx, dy)? {
+            cell.strength = strength;
+        }
+        Ok(())
+    }
+
+    fn set_variant(&mut self, dx: INT, dy: INT, variant: INT) -> ScriptResult<()> {
+        let variant = u8::try_from(variant)
+            .map_err(|_| format!("variant {} is not between 0 and 255", variant))?;
+        if let Some(cell) = self.get_mut(dx, dy)? {
+            cell.variant = variant;
+        }
+        Ok(())
+    }
+
+    fn set_temperature(&mut self, dx: INT, dy: INT, temperature: INT) -> ScriptResult<()> {
+        let temperature = i16::try_from(temperature)
+            .map_err(|_| format!("temperature {} is out of range", temperature))?;
+        if let Some(cell) = self.get_mut(dx, dy)? {
+            cell.temperature = temperature;
+        }
+        Ok(())
+    }
+
+    fn swap(&mut self, dx: INT, dy: INT, dx2: INT, dy2: INT) -> ScriptResult<()> {
+        let (index, index2) = (Neighborhood::index(dx, dy)?, Neighborhood::index(dx2, dy2)?);
+        if self.cells[index].element != Element::Indestructible
+            && self.cells[index2].element != Element::Indestructible
+        {
+            self.cells.swap(index, index2);
+        }
+        Ok(())
+    }
+
+    fn chance(&mut self, count: INT) -> ScriptResult<bool> {
+        if count < 1 {
+            return Err(format!("chance {} must be at least 1", count).into());
+        }
+        Ok(self.random.next() as INT % count == 0)
+    }
+}
+
+impl ElementScript {
+    pub fn compile(source: &str, budget: u64, step_budget: u64) -> Result<ElementScript, String> {
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(budget)
+            .set_max_call_levels(16)
+            .set_max_expr_depths(64, 32)
+            .set_max_string_size(1024)
+            .set_max_array_size(1024)
+            .set_max_map_size(1024)
+            .disable_symbol("eval")
+            .on_progress(|operations| {
+                OPERATIONS.with(|used| used.set(operations));
+                None
+            });
+        engine
+            .register_type_with_name::<Neighborhood>("Cells")
+            .register_fn("element", Neighborhood::element)
+            .register_fn("form", Neighborhood::form)
+            .register_fn("strength", Neighborhood::strength)
+            .register_fn("variant", Neighborhood::variant)
+            .register_fn("temperature", Neighborhood::temperature)
+            .register_fn("set_element", Neighborhood::set_element)
+            .register_fn("set_strength", Neighborhood::set_strength)
+            .register_fn("set_variant", Neighborhood::set_variant)
+            .register_fn("set_temperature", Neighborhood::set_temperature)
+            .register_fn("swap", Neighborhood::swap)
+            .register_fn("chance", Neighborhood::chance);
+        let ast = engine.compile(source).map_err(|error| error.to_string())?;
+        Ok(ElementScript {
+            engine,
+            ast,
+            stopped: AtomicBool::new(false),
+            step_budget,
+        })
+    }
+
+    // Run the script for a cell, returns true if it changed any cells, or the error that should
+    // stop the script
+    pub fn update(
+        &self,
+        element: Element,
+        x: usize,
+        y: usize,
+        sandbox: &mut SandBoxView,
+        usage: &mut ScriptUsage,
+        random: u32,
+    ) -> Result<bool, String> {
+        let operations = usage.operations(element);
+        if self.stopped.load(Ordering::Relaxed) || *operations >= self.step_budget {
+            return Ok(false);
+        }
+        // The outcome of a script is unknown, so keep scripted elements awake
+        sandbox.keep_awake(x, y);
+
+        let neighborhood = Neighborhood {
+            cells: std::array::from_fn(|index| *sandbox.get(x + index % 3 - 1, y + index / 3 - 1)),
+            random: PseudoRandom::with_seed(random as u64),
+        };
+        let mut scope = Scope::new();
+        scope.push("cells", neighborhood.clone());
+        OPERATIONS.with(|used| used.set(0));
+        let result = self
+            .engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .and_then(|_| {
+                scope
+                    .get_value::<Neighborhood>("cells")
+                    .ok_or_else(|| "the cells variable was replaced".into())
+            });
+        *operations += OPERATIONS.with(|used| used.get());
+        let changed = result.map_err(|error| error.to_string())?;
+
+        let mut any_changed = false;
+        for (index, cell) in changed.cells.into_iter().enumerate() {
+            if cell != neighborhood.cells[index] {
+                let (x, y) = (x + index % 3 - 1, y + index / 3 - 1);
+                *sandbox.get_mut(x, y) = cell;
+                sandbox.set_visited(x, y);
+                any_changed = true;
+            }
+        }
+        Ok(any_changed)
+    }
+
+    // Stop the script, returns false if it was stopped already
+    pub fn stop(&self) -> bool {
+        !self.stopped.swap(true, Ordering::Relaxed)
+    }
+}
+
+static SCRIPTS: OnceLock<Vec<Option<ElementScript>>> = OnceLock::new();
+
+// Script of an element, if it has one
+#[inline(always)]
+pub fn element_script(element: Element) -> Option<&'static ElementScript> {
+    SCRIPTS
+        .get()
+        .and_then(|scripts| scripts.get(element.id() as usize))
+        .and_then(Option::as_ref)
+}
+
+// Set the scripts of the elements, indexed by element id. This can only happen once, returns the
+// scripts when they were already installed.
+pub fn install_scripts(
+    scripts: Vec<Option<ElementScript>>,
+) -> Result<(), Vec<Option<ElementScript>>> {
+    SCRIPTS.set(scripts)
+}
+
+// Whether scripts were installed, after which no others can be installed
+pub fn scripts_installed() -> bool {
+    SCRIPTS.get().is_some()
+}
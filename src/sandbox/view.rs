@@ -0,0 +1,237 @@
+use std::marker::PhantomData;
+
+use crate::sandbox::*;
+
+// Cells around a chunk that may be accessed while updating it. Chunks updated at the same time
+// are two chunks apart, so their areas do not overlap.
+pub const CHUNK_REACH: usize = CHUNK_SIZE / 2;
+
+// The cells of a sandbox and their layout, shared between the views of a checkerboard phase
+pub(super) struct SharedCells {
+    pub(super) cells: *mut Cell,
+    pub(super) visited: *mut u16,
+    pub(super) visited_stride: usize,
+    pub(super) width: usize,
+    pub(super) height: usize,
+    pub(super) chunks_x: usize,
+    pub(super) chunks_y: usize,
+    pub(super) visited_state: bool,
+}
+
+unsafe impl Sync for SharedCells {}
+
+// Changes made through a view, applied to the chunks of the sandbox afterwards. A view can only
+// change the chunks directly around its own chunk, indexed from 0 (top left) to 8 (bottom right).
+#[derive(Default)]
+pub(super) struct ViewChanges {
+    pub(super) chunk_x: usize,
+    pub(super) chunk_y: usize,
+    pub(super) dirty: [Option<DirtyRect>; 9],
+    pub(super) woken: [bool; 9],
+}
+
+impl ViewChanges {
+    // Chunk coordinates of the surrounding chunks that were changed or woken
+    pub(super) fn neighbor(&self, index: usize) -> (usize, usize) {
+        (
+            (self.chunk_x + index % 3).wrapping_sub(1),
+            (self.chunk_y + index / 3).wrapping_sub(1),
+        )
+    }
+
+    fn index(&self, chunk_x: usize, chunk_y: usize) -> usize {
+        (chunk_x + 1 - self.chunk_x) + (chunk_y + 1 - self.chunk_y) * 3
+    }
+}
+
+// Access to the cells around a single chunk, so chunks that are far enough apart can be updated
+// in parallel. Accessing a cell outside of the chunk or its reach panics.
+pub struct SandBoxView<'a> {
+    cells: *mut Cell,
+    visited: *mut u16,
+    visited_stride: usize,
+    width: usize,
+    height: usize,
+    chunks_x: usize,
+    chunks_y: usize,
+    visited_state: bool,
+    // Accessible area, from the minimum up to the minimum plus the size
+    min_x: usize,
+    min_y: usize,
+    reach_width: usize,
+    reach_height: usize,
+    changes: ViewChanges,
+    _cells: PhantomData<&'a mut [Cell]>,
+}
+
+// Views only access their own area of the cells
+unsafe impl Send for SandBoxView<'_> {}
+
+impl<'a> SandBoxView<'a> {
+    // Safety: the cells must stay valid while the view is in use, and no other reference to the
+    // accessible area of this chunk may exist in the meantime
+    pub(super) unsafe fn new(shared: &SharedCells, chunk_x: usize, chunk_y: usize) -> Self {
+        let min_x = (chunk_x * CHUNK_SIZE).saturating_sub(CHUNK_REACH);
+        let min_y = (chunk_y * CHUNK_SIZE).saturating_sub(CHUNK_REACH);
+        SandBoxView {
+            cells: shared.cells,
+            visited: shared.visited,
+            visited_stride: shared.visited_stride,
+            width: shared.width,
+            height: shared.height,
+            chunks_x: shared.chunks_x,
+            chunks_y: shared.chunks_y,
+            visited_state: shared.visited_state,
+            min_x,
+            min_y,
+            reach_width: ((chunk_x + 1) * CHUNK_SIZE + CHUNK_REACH).min(shared.width) - min_x,
+            reach_height: ((chunk_y + 1) * CHUNK_SIZE + CHUNK_REACH).min(shared.height) - min_y,
+            changes: ViewChanges {
+                chunk_x,
+                chunk_y,
+                ..Default::default()
+            },
+            _cells: PhantomData,
+        }
+    }
+
+    pub(super) fn into_changes(self) -> ViewChanges {
+        self.changes
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> &Cell {
+        let index = self.index(x, y);
+        unsafe { &*self.cells.add(index) }
+    }
+
+    // Mutable access to a cell, which is assumed to change
+    pub fn get_mut(&mut self, x: usize, y: usize) -> &mut Cell {
+        self.mark_changed(x, y);
+        let index = self.index(x, y);
+        unsafe { &mut *self.cells.add(index) }
+    }
+
+    pub fn reduce_strength(&mut self, x: usize, y: usize, amount: u8) -> bool {
+        if self.get(x, y).strength > 0 {
+            let cell = self.get_mut(x, y);
+            cell.strength = cell.strength.saturating_sub(amount);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn clear_cell(&mut self, x: usize, y: usize) {
+        self.set_element(x, y, Element::Air);
+    }
+
+    pub fn set_element_with_strength(
+        &mut self,
+        x: usize,
+        y: usize,
+        element: Element,
+        strength: u8,
+    ) {
+        if self.get(x, y).element == Element::Indestructible {
+            // Cannot edit these blocks
+            return;
+        }
+        let cell = self.get_mut(x, y);
+        cell.element = element;
+        cell.variant = 0;
+        cell.strength = strength;
+        cell.temperature = element_type(element).temperature;
+        self.set_visited(x, y);
+    }
+
+    pub fn set_element(&mut self, x: usize, y: usize, element: Element) {
+        self.set_element_with_strength(x, y, element, element_type(element).strength);
+    }
+
+    pub fn swap(&mut self, x: usize, y: usize, x2: usize, y2: usize) {
+        let cell = *self.get(x, y);
+        let cell2 = *self.get(x2, y2);
+        if cell.element == Element::Indestructible || cell2.element == Element::Indestructible {
+            // Cannot edit these blocks
+            return;
+        }
+        // cell is moved to the place of cell 2, so becomes the second cell
+        *self.get_mut(x, y) = cell2;
+        *self.get_mut(x2, y2) = cell;
+        self.set_visited(x, y);
+        self.set_visited(x2, y2);
+    }
+
+    // Whether a cell was already updated during the current step
+    #[inline(always)]
+    pub fn is_visited(&self, x: usize, y: usize) -> bool {
+        self.check_reach(x, y);
+        let (index, mask) = visited_bit(x, y, self.visited_stride);
+        unsafe { (*self.visited.add(index) & mask != 0) == self.visited_state }
+    }
+
+    #[inline(always)]
+    pub fn set_visited(&mut self, x: usize, y: usize) {
+        self.check_reach(x, y);
+        let (index, mask) = visited_bit(x, y, self.visited_stride);
+        unsafe { set_visited_bit(&mut *self.visited.add(index), mask, self.visited_state) };
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Keep the chunk of a cell awake, see SandBox::keep_awake
+    pub fn keep_awake(&mut self, x: usize, y: usize) {
+        for (chunk_x, chunk_y) in chunks_around(x, y, self.chunks_x, self.chunks_y) {
+            let index = self.changes.index(chunk_x, chunk_y);
+            self.changes.woken[index] = true;
+        }
+    }
+
+    fn mark_changed(&mut self, x: usize, y: usize) {
+        let index = self.changes.index(x / CHUNK_SIZE, y / CHUNK_SIZE);
+        match &mut self.changes.dirty[index] {
+            Some(dirty) => dirty.include(x, y),
+            None => {
+                self.changes.dirty[index] = Some(DirtyRect {
+                    min_x: x,
+                    min_y: y,
+                    max_x: x,
+                    max_y: y,
+                })
+            }
+        }
+        self.keep_awake(x, y);
+    }
+
+    #[inline(always)]
+    fn index(&self, x: usize, y: usize) -> usize {
+        self.check_reach(x, y);
+        x + y * self.width
+    }
+
+    // Coordinates below the minimum wrap around to large values, so a single comparison per axis
+    // suffices
+    #[inline(always)]
+    fn check_reach(&self, x: usize, y: usize) {
+        if x.wrapping_sub(self.min_x) >= self.reach_width
+            || y.wrapping_sub(self.min_y) >= self.reach_height
+        {
+            out_of_reach(x, y);
+        }
+    }
+}
+
+#[cold]
+#[inline(never)]
+fn out_of_reach(x: usize, y: usize) -> ! {
+    panic!(
+        "cell ({}, {}) is out of reach of the chunk being updated",
+        x, y
+    );
+}
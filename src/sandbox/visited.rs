@@ -0,0 +1,78 @@
+use std::ops::Range;
+
+use crate::sandbox::*;
+
+// Number of cells per word of visited bits
+pub(super) const VISITED_WORD_CELLS: usize = u16::BITS as usize;
+
+// Views updated in parallel start and end at multiples of a word, so they never share a word
+const _: () = assert!(
+    CHUNK_SIZE.is_multiple_of(VISITED_WORD_CELLS) && CHUNK_REACH.is_multiple_of(VISITED_WORD_CELLS)
+);
+
+// The visited state of every cell, one bit per cell. A cell was visited during the current step
+// when its bit equals the visited state of the sandbox, which toggles each step. Every row starts
+// with a new word.
+#[derive(Clone)]
+pub(super) struct VisitedCells {
+    words: Vec<u16>,
+    stride: usize,
+}
+
+impl VisitedCells {
+    pub(super) fn new(width: usize, height: usize) -> Self {
+        let stride = width.div_ceil(VISITED_WORD_CELLS);
+        VisitedCells {
+            words: vec![0; stride * height],
+            stride,
+        }
+    }
+
+    #[inline(always)]
+    pub(super) fn get(&self, x: usize, y: usize) -> bool {
+        let (index, mask) = visited_bit(x, y, self.stride);
+        self.words[index] & mask != 0
+    }
+
+    #[inline(always)]
+    pub(super) fn set(&mut self, x: usize, y: usize, state: bool) {
+        let (index, mask) = visited_bit(x, y, self.stride);
+        set_visited_bit(&mut self.words[index], mask, state);
+    }
+
+    // Set the state of all cells in an area, rounded outwards to whole words
+    pub(super) fn fill(&mut self, xs: Range<usize>, ys: Range<usize>, state: bool) {
+        let words = xs.start / VISITED_WORD_CELLS..xs.end.div_ceil(VISITED_WORD_CELLS);
+        let value = if state { u16::MAX } else { 0 };
+        for y in ys {
+            let row = y * self.stride;
+            self.words[row + words.start..row + words.end].fill(value);
+        }
+    }
+
+    pub(super) fn as_mut_ptr(&mut self) -> *mut u16 {
+        self.words.as_mut_ptr()
+    }
+
+    pub(super) fn stride(&self) -> usize {
+        self.stride
+    }
+}
+
+// Index of the word containing the bit of a cell, and the mask of the bit in that word
+#[inline(always)]
+pub(super) fn visited_bit(x: usize, y: usize, stride: usize) -> (usize, u16) {
+    (
+        x / VISITED_WORD_CELLS + y * stride,
+        1 << (x % VISITED_WORD_CELLS),
+    )
+}
+
+#[inline(always)]
+pub(super) fn set_visited_bit(word: &mut u16, mask: u16, state: bool) {
+    if state {
+        *word |= mask;
+    } else {
+        *word &= !mask;
+    }
+}
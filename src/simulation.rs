@@ -1,15 +1,41 @@
+use std::ops::Range;
+#[cfg(feature = "scripting")]
+use std::sync::Mutex;
+
+#[cfg(not(target_family = "wasm"))]
+use rayon::prelude::*;
+
+#[cfg(feature = "bevy")]
 use bevy::prelude::*;
+#[cfg(feature = "bevy")]
 use bevy::utils::Instant;
 
-use crate::pseudo_random::PseudoRandom;
+use crate::pseudo_random::{cell_random, derived_random};
 use crate::sandbox::*;
 
-#[derive(Clone, Resource)]
+pub const DEFAULT_SEED: u64 = 12345;
+
+// Temperature increase per step of a cell that is burning
+const BURNING_HEAT: i16 = 16;
+// Number of steps between sparks sent out by a battery
+const BATTERY_PERIOD: u64 = 8;
+// Temperature increase of a heater per spark and the temperature it can reach
+const HEATER_HEAT: i16 = 250;
+const HEATER_MAX_TEMPERATURE: i16 = 1000;
+
+// Simulation state. The outcome of a simulation only depends on the initial sandbox, the seed and
+// the edits made in between steps, so runs with the same seed are reproducible on every platform.
+#[derive(Clone)]
+#[cfg_attr(feature = "bevy", derive(Resource))]
 pub struct Simulation {
     pub running: bool,
     pub step: bool,
     pub frame_time_ms: u128,
-    pub random: PseudoRandom,
+    pub seed: u64,
+    // Number of steps simulated so far
+    pub tick: u64,
+    // Errors of the element scripts that were stopped so far, for the caller to report
+    pub script_errors: Vec<String>,
 }
 
 impl Default for Simulation {
@@ -20,66 +46,151 @@ impl Default for Simulation {
 
 impl Simulation {
     pub fn new() -> Self {
+        Simulation::with_seed(DEFAULT_SEED)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
         Self {
             running: true,
             step: false,
             frame_time_ms: 0,
-            random: PseudoRandom::new(),
+            seed,
+            tick: 0,
+            script_errors: Vec::new(),
         }
     }
 }
 
 // System used to simulate the world a single step each frame
+#[cfg(feature = "bevy")]
 pub fn simulation_system(mut sandbox: Query<&mut SandBox>, mut simulation: ResMut<Simulation>) {
-    match sandbox.get_single_mut() {
-        Ok(mut sandbox) => {
-            simulation_step(simulation.as_mut(), sandbox.as_mut());
-        }
-        Err(_) => {
-            return;
-        }
+    if let Ok(mut sandbox) = sandbox.get_single_mut() {
+        let start = Instant::now();
+        simulation_step(simulation.as_mut(), sandbox.as_mut());
+        let duration = Instant::now() - start;
+        simulation.frame_time_ms = duration.as_millis();
     }
 }
 
+// Simulate the sandbox a single step, if the simulation is running or a single step was requested
 pub fn simulation_step(simulation: &mut Simulation, sandbox: &mut SandBox) {
-    let start = Instant::now();
     if simulation.running || simulation.step {
         simulation.step = false;
         let visited = sandbox.toggle_visited_state();
+        let (seed, tick) = (simulation.seed, simulation.tick);
+        #[cfg(feature = "scripting")]
+        let script_errors = Mutex::new(Vec::new());
+
+        // Only chunks that are awake at the start of the step are simulated
+        let (chunks_x, chunks_y) = sandbox.chunk_count();
+        let awake: Vec<bool> = (0..chunks_x * chunks_y)
+            .map(|index| sandbox.chunk(index % chunks_x, index / chunks_x).is_awake())
+            .collect();
+        let areas = awake_areas(sandbox, &awake);
+        conduct_heat(sandbox, &areas, seed, tick);
+        conduct_electricity(sandbox, &areas, tick);
+
+        // Chunks are updated in the four phases of a checkerboard pattern, updating the chunks
+        // of a phase in parallel. Switch X order every frame to avoid simulation artifacts.
         let (width, height) = (sandbox.width() - 1, sandbox.height() - 1);
-        for y in (1..height).rev() {
-            // Switch X order every frame to avoid simulation artifacts
-            if visited {
-                for x in 1..width {
-                    update_cell(x, y, sandbox, simulation.random.next());
+        let (first, second) = if visited { (0, 1) } else { (1, 0) };
+        for phase in [(first, 1), (second, 1), (first, 0), (second, 0)] {
+            let skip = |chunk_x: usize, chunk_y: usize| !awake[chunk_x + chunk_y * chunks_x];
+            sandbox.update_chunks(phase, skip, |view, chunk_x, chunk_y| {
+                let xs = (chunk_x * CHUNK_SIZE).max(1)..((chunk_x + 1) * CHUNK_SIZE).min(width);
+                let ys = (chunk_y * CHUNK_SIZE).max(1)..((chunk_y + 1) * CHUNK_SIZE).min(height);
+                let mut scripts = ChunkScripts::default();
+                for y in ys.rev() {
+                    if visited {
+                        for x in xs.clone() {
+                            update_cell(x, y, view, &mut scripts, cell_random(seed, tick, x, y));
+                        }
+                    } else {
+                        for x in xs.clone().rev() {
+                            update_cell(x, y, view, &mut scripts, cell_random(seed, tick, x, y));
+                        }
+                    }
                 }
-            } else {
-                for x in (1..width).rev() {
-                    update_cell(x, y, sandbox, simulation.random.next());
+                #[cfg(feature = "scripting")]
+                for (element, error) in scripts.errors {
+                    script_errors.lock().unwrap().push((element, chunk_y, chunk_x, error));
+                }
+            });
+        }
+        sandbox.end_chunk_step();
+
+        // Scripts with errors stop at the end of the step, so all chunks ran the same scripts.
+        // The error reported is that of the first chunk it happened in.
+        #[cfg(feature = "scripting")]
+        {
+            let mut script_errors = script_errors.into_inner().unwrap();
+            script_errors.sort_by_key(|&(element, chunk_y, chunk_x, _)| {
+                (element.id(), chunk_y, chunk_x)
+            });
+            script_errors.dedup_by_key(|(element, ..)| *element);
+            for (element, _, _, error) in script_errors {
+                if element_script(element).is_some_and(|script| script.stop()) {
+                    let error = format!("Stopped the script of {}: {}", element, error);
+                    simulation.script_errors.push(error);
                 }
             }
         }
+        simulation.tick += 1;
     }
-    let duration = Instant::now() - start;
-    simulation.frame_time_ms = duration.as_millis();
 }
 
-fn update_cell(x: usize, y: usize, sandbox: &mut SandBox, random: u32) {
+// Scripts run while updating a single chunk, with their own step budgets
+#[cfg(feature = "scripting")]
+#[derive(Default)]
+struct ChunkScripts {
+    usage: ScriptUsage,
+    errors: Vec<(Element, String)>,
+}
+
+#[cfg(not(feature = "scripting"))]
+#[derive(Default)]
+struct ChunkScripts {}
+
+#[cfg_attr(not(feature = "scripting"), allow(unused_variables))]
+fn update_cell(
+    x: usize,
+    y: usize,
+    sandbox: &mut SandBoxView,
+    scripts: &mut ChunkScripts,
+    random: u32,
+) {
     // Step 1: handle interactions with surrounding cells
-    let cell = sandbox.get(x, y).clone();
-    if cell.visited == sandbox.is_visited_state() {
+    if sandbox.is_visited(x, y) {
         // Visited this one already
         return;
     }
+    let cell = *sandbox.get(x, y);
     let cell_type = element_type(cell.element);
 
     // Generic element effects
-    if cell_type.has_flag(FLAG_IGNITES) {
-        handle_igniting_cell(x, y, sandbox, random);
+    if handle_temperature(x, y, sandbox, random) {
+        // Turned into another element
+        sandbox.set_visited(x, y);
+        return;
     }
 
-    if cell_type.has_flag(FLAG_ACIDIC) {
-        handle_acidic_cell(x, y, sandbox, random);
+    if handle_reactions(x, y, sandbox, random) {
+        // Turned into another element
+        sandbox.set_visited(x, y);
+        return;
+    }
+
+    // Scripted behaviour replaces the rest of the update when it changes anything
+    #[cfg(feature = "scripting")]
+    if let Some(script) = element_script(cell.element) {
+        match script.update(cell.element, x, y, sandbox, &mut scripts.usage, random) {
+            Ok(true) => {
+                sandbox.set_visited(x, y);
+                return;
+            }
+            Ok(false) => {}
+            Err(error) => scripts.errors.push((cell.element, error)),
+        }
     }
 
     if cell_type.has_flag(FLAG_IS_SOURCE) {
@@ -89,19 +200,24 @@ fn update_cell(x: usize, y: usize, sandbox: &mut SandBox, random: u32) {
     // Element-specific handling
     let mut marked_as_visited = match cell.element {
         Element::Air => update_air(x, y, sandbox),
-        Element::Water => update_water(x, y, sandbox, random),
         Element::Drain => update_drain(x, y, sandbox, random),
         Element::Fire => update_fire(x, y, sandbox, random),
         Element::Ash => update_ash(x, y, sandbox, random),
         Element::Lava => update_lava(x, y, sandbox, random),
         Element::Smoke => update_smoke(x, y, sandbox, random),
         Element::Life => update_life(x, y, sandbox),
-        Element::Iron => update_iron(x, y, sandbox, random),
         Element::Plant => update_plant(x, y, sandbox, random),
         Element::Seed => update_seed(x, y, sandbox),
         Element::TNT => update_explosive(Element::TNT, x, y, sandbox),
         Element::Gunpowder => update_explosive(Element::Gunpowder, x, y, sandbox),
         Element::Explosion => update_explosion(x, y, sandbox, random),
+        Element::Heater => update_heater(x, y, sandbox),
+        Element::Pump => update_pump(x, y, sandbox),
+        Element::Battery => {
+            // Batteries send out sparks without anything changing around them
+            sandbox.keep_awake(x, y);
+            false
+        }
         _ => false,
     };
 
@@ -124,46 +240,276 @@ fn update_cell(x: usize, y: usize, sandbox: &mut SandBox, random: u32) {
     }
 }
 
-fn handle_igniting_cell(x: usize, y: usize, sandbox: &mut SandBox, random: u32) {
-    for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
-        let neighbor_cell = sandbox.get(nx, ny);
-        let neighbor_type = element_type(neighbor_cell.element);
-        if neighbor_cell.element == Element::TNT || neighbor_cell.element == Element::Gunpowder {
-            sandbox.set_element_with_strength(
-                nx,
-                ny,
-                Element::Explosion,
-                neighbor_cell.strength,
-            );
-        } else if neighbor_type.has_flag(FLAG_BURNS) {
-            if neighbor_type.has_flag(FLAG_TURNS_INTO_ASH) && once_per(random, 3) {
-                sandbox.get_mut(nx, ny).dissolve_to(Element::Ash);
-            } else {
-                sandbox.get_mut(nx, ny).dissolve_to(Element::Fire);
+// Parts of the sandbox interior (inside the indestructible border) covered by awake chunks
+fn awake_areas(sandbox: &SandBox, awake: &[bool]) -> Vec<(Range<usize>, Range<usize>)> {
+    let (chunks_x, chunks_y) = sandbox.chunk_count();
+    let (width, height) = (sandbox.width() - 1, sandbox.height() - 1);
+    let mut areas = Vec::new();
+    for chunk_y in 0..chunks_y {
+        for chunk_x in 0..chunks_x {
+            if awake[chunk_x + chunk_y * chunks_x] {
+                areas.push((
+                    (chunk_x * CHUNK_SIZE).max(1)..((chunk_x + 1) * CHUNK_SIZE).min(width),
+                    (chunk_y * CHUNK_SIZE).max(1)..((chunk_y + 1) * CHUNK_SIZE).min(height),
+                ));
+            }
+        }
+    }
+    areas
+}
+
+// Compute a value for every cell of the areas without changing the sandbox, in parallel on
+// native targets. Returns the values of each area row by row.
+fn map_areas<T, F>(sandbox: &SandBox, areas: &[(Range<usize>, Range<usize>)], f: F) -> Vec<Vec<T>>
+where
+    T: Send,
+    F: Fn(&SandBox, usize, usize) -> T + Sync,
+{
+    let map_area = |(xs, ys): &(Range<usize>, Range<usize>)| {
+        ys.clone()
+            .flat_map(|y| xs.clone().map(move |x| (x, y)))
+            .map(|(x, y)| f(sandbox, x, y))
+            .collect()
+    };
+    #[cfg(not(target_family = "wasm"))]
+    return areas.par_iter().map(map_area).collect();
+    #[cfg(target_family = "wasm")]
+    return areas.iter().map(map_area).collect();
+}
+
+// Exchange heat between neighboring cells. New temperatures are computed from those of the
+// previous step only, so the outcome does not depend on the order in which cells are updated.
+fn conduct_heat(
+    sandbox: &mut SandBox,
+    areas: &[(Range<usize>, Range<usize>)],
+    seed: u64,
+    tick: u64,
+) {
+    let temperatures = map_areas(sandbox, areas, |sandbox, x, y| {
+        next_temperature(sandbox, x, y, cell_random(seed, tick, x, y))
+    });
+
+    let mut temperatures = temperatures.into_iter().flatten();
+    for (xs, ys) in areas {
+        for y in ys.clone() {
+            for x in xs.clone() {
+                sandbox.set_temperature(x, y, temperatures.next().unwrap());
             }
         }
     }
 }
 
-fn handle_acidic_cell(x: usize, y: usize, sandbox: &mut SandBox, random: u32) {
+fn next_temperature(sandbox: &SandBox, x: usize, y: usize, random: u32) -> i16 {
+    let cell = sandbox.get(x, y);
+    let cell_type = element_type(cell.element);
+    let temperature = cell.temperature as i32;
+    let mut flow = 0;
     for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
-        let neighbor_cell = sandbox.get_mut(nx, ny);
-        let neighbor_type = element_type(neighbor_cell.element);
-        if neighbor_type.has_flag(FLAG_DISSOLVES_IN_ACID)
-            && once_per(random, (neighbor_cell.strength / 2).max(2) as u32)
-        {
-            if sandbox.get_mut(nx, ny).dissolve_to(Element::Air) {
-                if once_per(random, 2) {
-                    sandbox.set_element(x, y, Element::Smoke);
-                } else {
-                    sandbox.clear_cell(x, y);
+        let neighbor = sandbox.get(nx, ny);
+        let conductivity = cell_type
+            .conductivity
+            .min(element_type(neighbor.element).conductivity) as i32;
+        flow += (neighbor.temperature as i32 - temperature) * conductivity;
+    }
+    let divisor = MAX_CONDUCTIVITY as i32 * 4 * cell_type.heat_capacity as i32;
+    let mut new_temperature = temperature + divide_randomly(flow, divisor, random >> 16);
+    if cell.element == Element::Air || cell.element == Element::Steam {
+        // Open air and steam slowly lose their heat to the environment
+        new_temperature +=
+            divide_randomly(AMBIENT_TEMPERATURE as i32 - new_temperature, 32, random);
+    }
+    new_temperature.clamp(i16::MIN as i32, i16::MAX as i32) as i16
+}
+
+// Divide, rounding up or down at random in proportion to the remainder. Small temperature
+// differences would never even out when always rounding towards zero.
+fn divide_randomly(value: i32, divisor: i32, random: u32) -> i32 {
+    let remainder = value % divisor;
+    if random % (divisor as u32) < remainder.unsigned_abs() {
+        value / divisor + remainder.signum()
+    } else {
+        value / divisor
+    }
+}
+
+// Move sparks through conductors following the rules of the Wireworld automaton: a spark head
+// becomes a tail, a tail becomes idle and an idle conductor becomes a head when one or two of its
+// eight neighbors are heads. Conductors next to a battery become a head whenever it sends out a
+// spark. Like heat, sparks are computed from the states of the previous step.
+fn conduct_electricity(sandbox: &mut SandBox, areas: &[(Range<usize>, Range<usize>)], tick: u64) {
+    let battery_pulse = tick.is_multiple_of(BATTERY_PERIOD);
+    let sparks = map_areas(sandbox, areas, |sandbox, x, y| {
+        next_spark(sandbox, x, y, battery_pulse)
+    });
+
+    let mut sparks = sparks.into_iter().flatten();
+    for (xs, ys) in areas {
+        for y in ys.clone() {
+            for x in xs.clone() {
+                if let Some(spark) = sparks.next().unwrap() {
+                    if sandbox.get(x, y).variant != spark {
+                        sandbox.get_mut(x, y).variant = spark;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The next spark state of a conductor, or None for other elements
+fn next_spark(sandbox: &SandBox, x: usize, y: usize, battery_pulse: bool) -> Option<u8> {
+    let cell = sandbox.get(x, y);
+    if !element_type(cell.element).has_flag(FLAG_CONDUCTIVE) {
+        return None;
+    }
+    let spark = match cell.variant {
+        SPARK_HEAD => SPARK_TAIL,
+        SPARK_TAIL => SPARK_NONE,
+        _ => {
+            let mut heads = 0;
+            let mut battery = false;
+            for (nx, ny) in [
+                (x - 1, y - 1),
+                (x, y - 1),
+                (x + 1, y - 1),
+                (x - 1, y),
+                (x + 1, y),
+                (x - 1, y + 1),
+                (x, y + 1),
+                (x + 1, y + 1),
+            ] {
+                let neighbor = sandbox.get(nx, ny);
+                if neighbor.element == Element::Battery {
+                    battery |= battery_pulse;
+                } else if neighbor.variant == SPARK_HEAD
+                    && element_type(neighbor.element).has_flag(FLAG_CONDUCTIVE)
+                {
+                    heads += 1;
                 }
             }
+            if battery || heads == 1 || heads == 2 {
+                SPARK_HEAD
+            } else {
+                SPARK_NONE
+            }
+        }
+    };
+    Some(spark)
+}
+
+// Devices are powered by a spark head in a conductor next to them
+fn is_powered(x: usize, y: usize, sandbox: &SandBoxView) -> bool {
+    [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+        .into_iter()
+        .any(|(nx, ny)| {
+            let neighbor = sandbox.get(nx, ny);
+            element_type(neighbor.element).has_flag(FLAG_CONDUCTIVE)
+                && neighbor.variant == SPARK_HEAD
+        })
+}
+
+// Temperature driven behaviour: burning, exploding, melting, freezing and boiling.
+// Returns true when the cell turned into another element.
+fn handle_temperature(x: usize, y: usize, sandbox: &mut SandBoxView, random: u32) -> bool {
+    let cell = sandbox.get(x, y);
+    let ignition_temperature = element_type(cell.element).ignition_temperature;
+    if ignition_temperature.is_some_and(|ignition| cell.temperature >= ignition) {
+        return handle_burning_cell(x, y, sandbox, random);
+    }
+    handle_phase_change(x, y, sandbox)
+}
+
+fn handle_burning_cell(x: usize, y: usize, sandbox: &mut SandBoxView, random: u32) -> bool {
+    let cell = sandbox.get_mut(x, y);
+    if cell.element == Element::TNT || cell.element == Element::Gunpowder {
+        let strength = cell.strength;
+        sandbox.set_element_with_strength(x, y, Element::Explosion, strength);
+        return true;
+    }
+    let cell_type = element_type(cell.element);
+    let burned_up = if cell_type.has_flag(FLAG_TURNS_INTO_ASH) && once_per(random, 3) {
+        cell.dissolve_to(Element::Ash)
+    } else {
+        cell.dissolve_to(Element::Fire)
+    };
+    if !burned_up {
+        // Burning releases heat
+        cell.temperature = cell.temperature.saturating_add(BURNING_HEAT);
+        ignite_neighbors(x, y, sandbox);
+    } else if cell.element == Element::Fire {
+        cell.temperature = cell
+            .temperature
+            .max(element_type(Element::Fire).temperature);
+    }
+    burned_up
+}
+
+// Phase transitions from the element table, such as water freezing into ice or boiling into steam.
+// The cell keeps its temperature. It does not immediately turn back, as the element it turns into
+// has a threshold to turn back that lies past the one just crossed, e.g. ice melts above 2 degrees
+// while water freezes below -2 degrees.
+fn handle_phase_change(x: usize, y: usize, sandbox: &mut SandBoxView) -> bool {
+    let cell = sandbox.get(x, y);
+    let cell_type = element_type(cell.element);
+    let temperature = cell.temperature;
+    let transition = match (cell_type.above, cell_type.below) {
+        (Some(above), _) if temperature > above.temperature => above,
+        (_, Some(below)) if temperature < below.temperature => below,
+        _ => return false,
+    };
+    let element = match transition.quenched {
+        Some(quenched) if is_quenched(x, y, sandbox) => quenched,
+        _ => transition.element,
+    };
+    sandbox.set_element(x, y, element);
+    sandbox.get_mut(x, y).temperature = temperature;
+    true
+}
+
+fn is_quenched(x: usize, y: usize, sandbox: &SandBoxView) -> bool {
+    [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+        .into_iter()
+        .any(|(nx, ny)| element_type(sandbox.get(nx, ny).element).has_flag(FLAG_QUENCHES))
+}
+
+// Reactions from the reaction table with the four neighbors, at most one per neighbor.
+// Returns true when the cell turned into another element.
+fn handle_reactions(x: usize, y: usize, sandbox: &mut SandBoxView, random: u32) -> bool {
+    let element = sandbox.get(x, y).element;
+    let reactions = element_reactions(element);
+    if reactions.is_empty() {
+        return false;
+    }
+    for (index, (nx, ny)) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+        .into_iter()
+        .enumerate()
+    {
+        let neighbor = *sandbox.get(nx, ny);
+        let Some(reaction) = reactions
+            .iter()
+            .find(|reaction| reaction.touching.matches(neighbor.element))
+        else {
+            continue;
+        };
+        // Reactions happen by chance, so stay awake until they do
+        sandbox.keep_awake(x, y);
+        let chance = reaction.chance_with(neighbor.strength);
+        if !once_per(derived_random(random, index as u32), chance) {
+            continue;
+        }
+        if let Some(product) = reaction.touching_product {
+            sandbox.get_mut(nx, ny).dissolve_to(product);
+        }
+        if let Some(product) = reaction.product {
+            if sandbox.get_mut(x, y).dissolve_to(product) {
+                return true;
+            }
         }
     }
+    false
 }
 
-fn handle_powder_form(sandbox: &mut SandBox, x: usize, y: usize, random: u32) -> bool {
+fn handle_powder_form(sandbox: &mut SandBoxView, x: usize, y: usize, random: u32) -> bool {
     // Can we fall down?
     let below_element = sandbox.get(x, y + 1).element;
     let below_element_type = element_type(below_element);
@@ -191,8 +537,8 @@ fn handle_powder_form(sandbox: &mut SandBox, x: usize, y: usize, random: u32) ->
     false
 }
 
-fn handle_liquid_form(sandbox: &mut SandBox, x: usize, y: usize, random: u32) -> bool {
-    let cell = sandbox.get(x, y).clone();
+fn handle_liquid_form(sandbox: &mut SandBoxView, x: usize, y: usize, random: u32) -> bool {
+    let cell = *sandbox.get(x, y);
     let cell_element_type = element_type(cell.element);
 
     let random_60 = random % 60;
@@ -217,9 +563,10 @@ fn handle_liquid_form(sandbox: &mut SandBox, x: usize, y: usize, random: u32) ->
         return true;
     }
 
-    // Liquid flows sideways. Strength of the cell indicates the speed of sideways flow.
+    // Liquid flows sideways. Strength of the cell indicates the speed of sideways flow, limited
+    // to the cells that can be reached while updating a chunk.
     let check_left = once_per(random, 2);
-    for n in 1..cell.strength as usize {
+    for n in 1..(cell.strength as usize).min(CHUNK_REACH) {
         let check_x_opt = if check_left {
             if x > n {
                 Some(x - n)
@@ -254,11 +601,30 @@ fn handle_liquid_form(sandbox: &mut SandBox, x: usize, y: usize, random: u32) ->
         }
     }
 
+    // Flowing depends on chance, so stay awake while there is somewhere to go
+    if [
+        (x - 1, y + 1),
+        (x, y + 1),
+        (x + 1, y + 1),
+        (x - 1, y),
+        (x + 1, y),
+    ]
+    .into_iter()
+    .any(|(nx, ny)| {
+        let neighbor = sandbox.get(nx, ny);
+        let neighbor_type = element_type(neighbor.element);
+        neighbor_type.form == ElementForm::Gas
+            || (neighbor_type.form == ElementForm::Liquid
+                && neighbor.element != cell.element
+                && neighbor_type.weight < cell_element_type.weight)
+    }) {
+        sandbox.keep_awake(x, y);
+    }
     true
 }
 
-fn handle_gas_form(sandbox: &mut SandBox, x: usize, y: usize, random: u32) -> bool {
-    let cell = sandbox.get(x, y).clone();
+fn handle_gas_form(sandbox: &mut SandBoxView, x: usize, y: usize, random: u32) -> bool {
+    let cell = *sandbox.get(x, y);
     let cell_element_type = element_type(cell.element);
 
     // Move in a random direction, with a tendency upwards
@@ -277,15 +643,31 @@ fn handle_gas_form(sandbox: &mut SandBox, x: usize, y: usize, random: u32) -> bo
         sandbox.swap(x, y, nx, ny);
         return true;
     }
+    if [(x + 1, y), (x - 1, y), (x, y - 1)]
+        .into_iter()
+        .any(|(nx, ny)| {
+            let neighbor_element = sandbox.get(nx, ny).element;
+            let neighbor_type = element_type(neighbor_element);
+            neighbor_type.form == ElementForm::Gas
+                && cell.element != neighbor_element
+                && neighbor_type.weight > cell_element_type.weight
+        })
+    {
+        sandbox.keep_awake(x, y);
+    }
     false
 }
 
 fn handle_source_cell(
     x: usize,
     y: usize,
-    sandbox: &mut SandBox,
+    sandbox: &mut SandBoxView,
     cell_type: &ElementType,
 ) {
+    // Sources keep their temperature, so fire and lava sources keep heating their surroundings
+    if sandbox.get(x, y).temperature != cell_type.temperature {
+        sandbox.get_mut(x, y).temperature = cell_type.temperature;
+    }
     for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
         if sandbox.get(nx, ny).element == Element::Air {
             sandbox.set_element(nx, ny, cell_type.source_element);
@@ -293,36 +675,7 @@ fn handle_source_cell(
     }
 }
 
-fn update_water(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
-    let (nx, ny) = match random % 4 {
-        0 => (x - 1, y),
-        1 => (x + 1, y),
-        2 => (x, y - 1),
-        _ => (x, y + 1),
-    };
-    let neighbor_element = sandbox.get(nx, ny).element;
-    match neighbor_element {
-        Element::Acid => {
-            sandbox.get_mut(nx, ny).dissolve_to(Element::Water);
-            return false;
-        }
-        Element::Lava => {
-            if sandbox.get_mut(nx, ny).dissolve_to(Element::Rock) {
-                sandbox.clear_cell(x, y);
-            }
-            return false;
-        }
-        Element::Fire => {
-            sandbox.clear_cell(x, y);
-            sandbox.set_element(nx, ny, Element::Water);
-            return true;
-        }
-        _ => {}
-    }
-    false
-}
-
-fn update_drain(x: usize, y: usize, sandbox: &mut SandBox, _random: u32) -> bool {
+fn update_drain(x: usize, y: usize, sandbox: &mut SandBoxView, _random: u32) -> bool {
     // Remove any liquid on top, left or right of this cell
     let element_form = element_type(sandbox.get(x, y - 1).element).form;
     if element_form == ElementForm::Liquid {
@@ -342,7 +695,8 @@ fn update_drain(x: usize, y: usize, sandbox: &mut SandBox, _random: u32) -> bool
     false
 }
 
-fn update_fire(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
+fn update_fire(x: usize, y: usize, sandbox: &mut SandBoxView, random: u32) -> bool {
+    ignite_neighbors(x, y, sandbox);
     // Reduce fire strength over time
     if once_per(random, 2) && sandbox.get_mut(x, y).dissolve_to(Element::Air) {
         sandbox.set_element(x, y, Element::Smoke);
@@ -351,29 +705,45 @@ fn update_fire(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
     false
 }
 
-fn update_ash(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
+// Heat neighbors that can burn or explode up to the temperature of a fire or burning cell. Fire
+// rises away before heat conduction alone warms them up enough, and cells heated to just their
+// ignition temperature cool down again before they burn up, which would stop fire spreading along
+// e.g. a fuse. Heated neighbors start burning in the next step, so fire does not race through a
+// whole line of cells in the order they are updated.
+fn ignite_neighbors(x: usize, y: usize, sandbox: &mut SandBoxView) {
+    let temperature = sandbox.get(x, y).temperature;
+    for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
+        let neighbor = sandbox.get(nx, ny);
+        let ignites = element_type(neighbor.element)
+            .ignition_temperature
+            .is_some_and(|ignition| ignition <= temperature);
+        if ignites && neighbor.temperature < temperature {
+            sandbox.get_mut(nx, ny).temperature = temperature;
+            sandbox.set_visited(nx, ny);
+        }
+    }
+}
+
+fn update_ash(x: usize, y: usize, sandbox: &mut SandBoxView, random: u32) -> bool {
+    sandbox.keep_awake(x, y);
     if once_per(random, 100) && sandbox.get_mut(x, y).dissolve_to(Element::Air) {
         return true;
     }
     false
 }
 
-fn update_lava(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
-    let cell = sandbox.get_mut(x, y);
-    // Cool down when no longer at max hotness
-    if once_per(random, 2) && cell.strength < element_type(Element::Lava).strength {
-        if sandbox.get_mut(x, y).dissolve_to(Element::Rock) {
-            return true;
-        }
-    }
+fn update_lava(x: usize, y: usize, sandbox: &mut SandBoxView, random: u32) -> bool {
     // Give off sparks
-    if once_per(random, 100) && sandbox.get(x, y - 1).element == Element::Air {
-        sandbox.set_element(x, y - 1, Element::Fire);
+    if sandbox.get(x, y - 1).element == Element::Air {
+        sandbox.keep_awake(x, y);
+        if once_per(random, 100) {
+            sandbox.set_element(x, y - 1, Element::Fire);
+        }
     }
     false
 }
 
-fn update_smoke(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
+fn update_smoke(x: usize, y: usize, sandbox: &mut SandBoxView, random: u32) -> bool {
     if once_per(random, 2) && sandbox.get_mut(x, y).dissolve_to(Element::Air) {
         sandbox.clear_cell(x, y);
         return true;
@@ -381,27 +751,7 @@ fn update_smoke(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool
     false
 }
 
-fn update_iron(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
-    let mut rusty_neighbor = false;
-    for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
-        let element = sandbox.get(nx, ny).element;
-        if element_type(element).has_flag(FLAG_CAUSES_RUST) {
-            rusty_neighbor = true;
-            break;
-        }
-    }
-    if rusty_neighbor {
-        // Rust iron by reducing its strength somewhat randomly
-        if once_per(random, 3) && !sandbox.reduce_strength(x, y, 1) {
-            // Turn into rust
-            sandbox.set_element(x, y, Element::Rust);
-            return true;
-        }
-    }
-    false
-}
-
-fn update_seed(x: usize, y: usize, sandbox: &mut SandBox) -> bool {
+fn update_seed(x: usize, y: usize, sandbox: &mut SandBoxView) -> bool {
     // Check if we have water and nutrition
     let mut nutrition = false;
     let mut water = false;
@@ -430,13 +780,14 @@ fn update_seed(x: usize, y: usize, sandbox: &mut SandBox) -> bool {
     }
 }
 
-fn update_plant(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
+fn update_plant(x: usize, y: usize, sandbox: &mut SandBoxView, random: u32) -> bool {
     let (cell_strength, cell_variant) = {
         let cell = sandbox.get(x, y);
         (cell.strength, cell.variant)
     };
     if cell_variant <= 1 {
         // Sometimes turns into seed
+        sandbox.keep_awake(x, y);
         if once_per(random, 5) {
             sandbox.set_element(x, y, Element::Seed);
         }
@@ -473,7 +824,13 @@ fn update_plant(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool
         // Not growing anymore
         return false;
     }
-    // Plant is still growing
+    // Plant is still growing, if there is room to grow
+    if [(x - 1, y), (x + 1, y), (x, y - 1)]
+        .into_iter()
+        .any(|(nx, ny)| element_type(sandbox.get(nx, ny).element).has_flag(FLAG_ALLOW_PLANT))
+    {
+        sandbox.keep_awake(x, y);
+    }
     let (nx, ny) = match random % 1000 {
         0 | 1 => (x - 1, y),
         2 | 3 => (x + 1, y),
@@ -491,7 +848,7 @@ fn update_plant(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool
     false
 }
 
-fn update_explosive(element: Element, x: usize, y: usize, sandbox: &mut SandBox) -> bool {
+fn update_explosive(element: Element, x: usize, y: usize, sandbox: &mut SandBoxView) -> bool {
     let strength = sandbox.get(x, y).strength;
     if strength == element_type(element).strength {
         return false;
@@ -500,7 +857,7 @@ fn update_explosive(element: Element, x: usize, y: usize, sandbox: &mut SandBox)
     true
 }
 
-fn update_explosion(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> bool {
+fn update_explosion(x: usize, y: usize, sandbox: &mut SandBoxView, random: u32) -> bool {
     if !sandbox.reduce_strength(x, y, 1) {
         sandbox.set_element(x, y, Element::Fire);
         return true;
@@ -512,7 +869,7 @@ fn update_explosion(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> b
         _ => [(x, y - 1), (x, y + 1)],
     };
     for (nx, ny) in neighbors {
-        let neighbor = sandbox.get_mut(nx, ny);
+        let neighbor = sandbox.get(nx, ny);
         if neighbor.element == Element::TNT || neighbor.element == Element::Gunpowder {
             let explosion_strength = if neighbor.strength + strength < 255 {
                 neighbor.strength + strength
@@ -545,7 +902,33 @@ fn update_explosion(x: usize, y: usize, sandbox: &mut SandBox, random: u32) -> b
     true
 }
 
-fn update_air(x: usize, y: usize, sandbox: &mut SandBox) -> bool {
+fn update_heater(x: usize, y: usize, sandbox: &mut SandBoxView) -> bool {
+    let temperature = sandbox.get(x, y).temperature;
+    if is_powered(x, y, sandbox) && temperature < HEATER_MAX_TEMPERATURE {
+        sandbox.get_mut(x, y).temperature = (temperature + HEATER_HEAT).min(HEATER_MAX_TEMPERATURE);
+    }
+    false
+}
+
+fn update_pump(x: usize, y: usize, sandbox: &mut SandBoxView) -> bool {
+    if !is_powered(x, y, sandbox) {
+        return false;
+    }
+    // Move liquid from below the pump to above it
+    let below = *sandbox.get(x, y + 1);
+    if element_type(below.element).form == ElementForm::Liquid
+        && element_type(sandbox.get(x, y - 1).element).form == ElementForm::Gas
+    {
+        sandbox.set_element_with_strength(x, y - 1, below.element, below.strength);
+        let pumped = sandbox.get_mut(x, y - 1);
+        pumped.variant = below.variant;
+        pumped.temperature = below.temperature;
+        sandbox.clear_cell(x, y + 1);
+    }
+    false
+}
+
+fn update_air(x: usize, y: usize, sandbox: &mut SandBoxView) -> bool {
     let mut living_neighbors = 0;
     if sandbox.get(x - 1, y - 1).element == Element::Life {
         living_neighbors += 1;
@@ -578,7 +961,7 @@ fn update_air(x: usize, y: usize, sandbox: &mut SandBox) -> bool {
     false
 }
 
-fn update_life(x: usize, y: usize, sandbox: &mut SandBox) -> bool {
+fn update_life(x: usize, y: usize, sandbox: &mut SandBoxView) -> bool {
     let mut living_neighbors = 0;
     if sandbox.get(x - 1, y - 1).element == Element::Life {
         living_neighbors += 1;
@@ -604,7 +987,7 @@ fn update_life(x: usize, y: usize, sandbox: &mut SandBox) -> bool {
     if sandbox.get(x + 1, y + 1).element == Element::Life {
         living_neighbors += 1;
     }
-    if living_neighbors < 2 || living_neighbors > 3 {
+    if !(2..=3).contains(&living_neighbors) {
         sandbox.set_element(x, y, Element::Air);
         return true;
     }
@@ -613,7 +996,7 @@ fn update_life(x: usize, y: usize, sandbox: &mut SandBox) -> bool {
 }
 
 pub fn random_neighbor_x(x: usize, random: u32) -> usize {
-    if random % 2 == 0 {
+    if random.is_multiple_of(2) {
         x + 1
     } else {
         x - 1
@@ -621,7 +1004,7 @@ pub fn random_neighbor_x(x: usize, random: u32) -> usize {
 }
 
 pub fn random_other_neighbor_x(x: usize, random: u32) -> usize {
-    if random % 2 == 0 {
+    if random.is_multiple_of(2) {
         x - 1
     } else {
         x + 1
@@ -629,5 +1012,5 @@ pub fn random_other_neighbor_x(x: usize, random: u32) -> usize {
 }
 
 fn once_per(random: u32, count: u32) -> bool {
-    random % count == 0
+    random.is_multiple_of(count)
 }
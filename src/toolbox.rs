@@ -1,20 +1,22 @@
 use std::fmt;
 
+#[cfg(feature = "bevy")]
 use bevy::prelude::Resource;
 
-use crate::{pseudo_random::PseudoRandom, sandbox::*};
+use crate::{history::Stroke, pseudo_random::PseudoRandom, sandbox::*};
 
 // Tools for editing the world
+#[repr(u8)]
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Tool {
-    Pixel,
-    Circle,
-    Square,
-    Spray,
-    Fill,
+    Pixel = 0,
+    Circle = 1,
+    Square = 2,
+    Spray = 3,
+    Fill = 4,
 }
 
-#[derive(Resource)]
+#[cfg_attr(feature = "bevy", derive(Resource))]
 pub struct ToolBox {
     pub tool: Tool,
     pub element: Element,
@@ -23,7 +25,26 @@ pub struct ToolBox {
 }
 
 impl ToolBox {
+    // Toolbox with a seeded random generator, so randomized tools (spray) are reproducible
+    pub fn with_seed(seed: u64) -> Self {
+        Self {
+            random: PseudoRandom::with_seed(seed),
+            ..Default::default()
+        }
+    }
+
     pub fn apply(&mut self, sandbox: &mut SandBox, x: usize, y: usize) {
+        self.apply_tracked(sandbox, x, y, None);
+    }
+
+    // Apply the tool, remembering the original state of every edited cell in the stroke
+    pub fn apply_tracked(
+        &mut self,
+        sandbox: &mut SandBox,
+        x: usize,
+        y: usize,
+        mut stroke: Option<&mut Stroke>,
+    ) {
         let half_size = self.tool_size / 2;
         let remainder = if half_size == 0 {
             1
@@ -44,7 +65,7 @@ impl ToolBox {
         };
         match self.tool {
             Tool::Pixel => {
-                sandbox.set_element(x, y, self.element);
+                paint(sandbox, x, y, self.element, &mut stroke);
             }
             Tool::Circle => {
                 let radius_sq = (half_size * half_size) as isize;
@@ -53,7 +74,7 @@ impl ToolBox {
                         let dx = (cx as isize - x as isize).abs();
                         let dy = (cy as isize - y as isize).abs();
                         if dx * dx + dy * dy <= radius_sq {
-                            sandbox.set_element(cx, cy, self.element);
+                            paint(sandbox, cx, cy, self.element, &mut stroke);
                         }
                     }
                 }
@@ -61,11 +82,15 @@ impl ToolBox {
             Tool::Square => {
                 for cy in y1..y2 {
                     for cx in x1..x2 {
-                        sandbox.set_element(cx, cy, self.element);
+                        paint(sandbox, cx, cy, self.element, &mut stroke);
                     }
                 }
             }
             Tool::Spray => {
+                if x2 <= x1 || y2 <= y1 {
+                    // Nothing to spray on at the border
+                    return;
+                }
                 let radius_sq = (half_size * half_size) as isize;
                 let count = if half_size > 3 { half_size / 3 } else { 1 };
                 for _ in 0..count {
@@ -74,7 +99,7 @@ impl ToolBox {
                     let dx = (cx as isize - x as isize).abs();
                     let dy = (cy as isize - y as isize).abs();
                     if dx * dx + dy * dy <= radius_sq {
-                        sandbox.set_element(cx, cy, self.element);
+                        paint(sandbox, cx, cy, self.element, &mut stroke);
                     }
                 }
             }
@@ -86,16 +111,15 @@ impl ToolBox {
                 {
                     return;
                 }
-                sandbox.set_element(x, y, self.element);
+                paint(sandbox, x, y, self.element, &mut stroke);
                 checklist.push((x, y));
-                while !checklist.is_empty() {
-                    let (x, y) = checklist.pop().unwrap();
+                while let Some((x, y)) = checklist.pop() {
                     for (nx, ny) in [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)] {
                         let neighbor_element = sandbox.get(nx, ny).element;
                         if neighbor_element == element_to_replace
                             && neighbor_element != Element::Indestructible
                         {
-                            sandbox.set_element(nx, ny, self.element);
+                            paint(sandbox, nx, ny, self.element, &mut stroke);
                             checklist.push((nx, ny));
                         }
                     }
@@ -105,6 +129,19 @@ impl ToolBox {
     }
 }
 
+fn paint(
+    sandbox: &mut SandBox,
+    x: usize,
+    y: usize,
+    element: Element,
+    stroke: &mut Option<&mut Stroke>,
+) {
+    match stroke {
+        Some(stroke) => stroke.edit(sandbox, x, y, |sandbox| sandbox.set_element(x, y, element)),
+        None => sandbox.set_element(x, y, element),
+    }
+}
+
 impl Default for ToolBox {
     fn default() -> Self {
         Self {
@@ -116,6 +153,21 @@ impl Default for ToolBox {
     }
 }
 
+impl TryFrom<u8> for Tool {
+    type Error = u8;
+
+    fn try_from(id: u8) -> Result<Self, Self::Error> {
+        match id {
+            0 => Ok(Tool::Pixel),
+            1 => Ok(Tool::Circle),
+            2 => Ok(Tool::Square),
+            3 => Ok(Tool::Spray),
+            4 => Ok(Tool::Fill),
+            _ => Err(id),
+        }
+    }
+}
+
 impl fmt::Display for Tool {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{:?}", self)
@@ -0,0 +1,97 @@
+// Tests of the headless runner, running its binary on files in a temporary directory
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use falling_rust::sandbox::*;
+use falling_rust::simulation::{simulation_step, Simulation};
+
+// Empty directory of a test, which is also the working directory of the runner so no elements.ron
+// is picked up
+fn test_directory(name: &str) -> PathBuf {
+    let directory =
+        std::env::temp_dir().join(format!("falling-rust-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&directory);
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn run_cli(directory: &Path, arguments: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_falling-rust-cli"))
+        .args(arguments)
+        .current_dir(directory)
+        .output()
+        .unwrap()
+}
+
+fn sample_sandbox() -> SandBox {
+    let mut sandbox = SandBox::new(48, 32);
+    for x in 10..30 {
+        sandbox.set_element(x, 5, Element::Sand);
+        sandbox.set_element(x, 8, Element::Water);
+    }
+    sandbox.set_element(40, 28, Element::Lava);
+    sandbox
+}
+
+// Loading a sandbox, simulating it and saving the result gives the same sandbox as simulating it
+// in process with the same seed
+#[test]
+fn load_step_save() {
+    let directory = test_directory("load-step-save");
+    let sandbox = sample_sandbox();
+    sandbox
+        .save_file(directory.join("input.sandbox").to_str().unwrap())
+        .unwrap();
+
+    let output = run_cli(
+        &directory,
+        &[
+            "input.sandbox",
+            "--steps",
+            "40",
+            "--seed",
+            "7",
+            "--save",
+            "output.sandbox",
+            "--png",
+            "output.png",
+            "--scale",
+            "2",
+        ],
+    );
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let mut expected = sandbox;
+    let mut simulation = Simulation::with_seed(7);
+    for _ in 0..40 {
+        simulation_step(&mut simulation, &mut expected);
+    }
+    let saved = SandBox::load_file(directory.join("output.sandbox").to_str().unwrap()).unwrap();
+    assert_eq!(
+        (saved.width(), saved.height()),
+        (expected.width(), expected.height())
+    );
+    for y in 0..saved.height() {
+        for x in 0..saved.width() {
+            assert_eq!(saved.get(x, y), expected.get(x, y), "cell ({}, {})", x, y);
+        }
+    }
+    let png = image::open(directory.join("output.png")).unwrap();
+    assert_eq!((png.width(), png.height()), (96, 64));
+
+    std::fs::remove_dir_all(&directory).unwrap();
+}
+
+#[test]
+fn missing_input_fails() {
+    let directory = test_directory("missing-input");
+    let output = run_cli(&directory, &["missing.sandbox", "--steps", "1"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Cannot load missing.sandbox"));
+    std::fs::remove_dir_all(&directory).unwrap();
+}
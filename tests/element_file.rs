@@ -0,0 +1,83 @@
+// Tests of the checks on element definitions files
+
+use falling_rust::sandbox::*;
+
+// The built-in elements pass the checks that are done on element files
+#[test]
+fn builtin_elements_are_valid() {
+    assert!(parse_element_definitions("[]").is_ok());
+}
+
+// Ice melting below the temperature water freezes at would change back and forth
+#[test]
+fn overlapping_transitions_are_rejected() {
+    for text in [
+        r#"[(name: "Ice", above: (temperature: -5, element: "Water"))]"#,
+        r#"[(name: "Water", below: (temperature: 5, element: "Ice"))]"#,
+        r#"[(name: "Steam", below: (temperature: 120, element: "Water"))]"#,
+    ] {
+        assert!(matches!(
+            parse_element_definitions(text),
+            Err(ElementFileError::InvalidValue { .. })
+        ));
+    }
+}
+
+#[test]
+fn flags_are_parsed_by_name() {
+    let definitions = parse_element_definitions(
+        r#"[(name: "Salt", form: Powder, color: (240, 240, 232), flags: [DissolvesInAcid, Wet])]"#,
+    )
+    .unwrap();
+    let salt = definitions.element_types.last().unwrap();
+    assert_eq!(salt.flags, FLAG_DISSOLVES_IN_ACID | FLAG_WET);
+    assert!(matches!(
+        parse_element_definitions(r#"[(name: "Sand", flags: [Sticky])]"#),
+        Err(ElementFileError::Parse(_))
+    ));
+}
+
+// Liquids cannot flow further than the cells reachable while updating a chunk
+#[test]
+fn liquid_strength_is_limited() {
+    let text = format!(r#"[(name: "Water", strength: {})]"#, CHUNK_REACH);
+    assert!(parse_element_definitions(&text).is_ok());
+    let text = format!(r#"[(name: "Water", strength: {})]"#, CHUNK_REACH + 1);
+    assert!(matches!(
+        parse_element_definitions(&text),
+        Err(ElementFileError::InvalidValue {
+            property: "strength",
+            ..
+        })
+    ));
+}
+
+// Lava quenched by water turns into obsidian, which element files can change or turn off
+#[test]
+fn quenched_transitions_are_parsed() {
+    let definitions = parse_element_definitions(
+        r#"[
+            (name: "Lava", below: (temperature: 900, element: "Rock", quenched: "Glass")),
+            (name: "Water", flags: [Wet]),
+        ]"#,
+    )
+    .unwrap();
+    let types = &definitions.element_types;
+    let lava = types[Element::Lava.id() as usize].below.unwrap();
+    assert_eq!(lava.quenched, Some(Element::Glass));
+    assert!(!types[Element::Water.id() as usize].has_flag(FLAG_QUENCHES));
+    assert!(types[Element::Ice.id() as usize].has_flag(FLAG_QUENCHES));
+
+    let builtin = parse_element_definitions("[]").unwrap();
+    let lava = builtin.element_types[Element::Lava.id() as usize].below;
+    assert_eq!(lava.unwrap().quenched, Some(Element::Obsidian));
+    assert!(matches!(
+        parse_element_definitions(
+            r#"[(name: "Lava", below: (temperature: 900, element: "Rock", quenched: "Gold"))]"#
+        ),
+        Err(ElementFileError::UnknownElement {
+            property: "below",
+            ..
+        })
+    ));
+}
@@ -0,0 +1,116 @@
+// Tests of saving and loading sandboxes in the sandbox file format
+
+use falling_rust::sandbox::*;
+use falling_rust::simulation::{simulation_step, Simulation};
+
+// Offsets of the header fields and the first run of cells in a saved sandbox
+const VERSION_OFFSET: usize = 4;
+const WIDTH_OFFSET: usize = 6;
+const FIRST_RUN_OFFSET: usize = 14;
+
+fn save(sandbox: &SandBox) -> Vec<u8> {
+    let mut data = Vec::new();
+    sandbox.save_to(&mut data).unwrap();
+    data
+}
+
+fn load(data: &[u8]) -> Result<SandBox, SandBoxFileError> {
+    SandBox::load_from(&mut &data[..])
+}
+
+// A small sandbox with varied cells, simulated for a while so cells differ in variant, strength
+// and temperature
+fn sample_sandbox() -> SandBox {
+    let mut sandbox = SandBox::new(40, 30);
+    for x in 5..35 {
+        sandbox.set_element(x, 25, Element::Wood);
+        sandbox.set_element(x, 5, Element::Sand);
+        sandbox.set_element(x, 10, Element::Water);
+    }
+    sandbox.set_element(20, 24, Element::Fire);
+    sandbox.set_element(8, 20, Element::Lava);
+    let mut simulation = Simulation::new();
+    for _ in 0..20 {
+        simulation_step(&mut simulation, &mut sandbox);
+    }
+    sandbox
+}
+
+#[test]
+fn save_and_load_round_trip() {
+    let sandbox = sample_sandbox();
+    let loaded = load(&save(&sandbox)).unwrap();
+
+    assert_eq!(
+        (loaded.width(), loaded.height()),
+        (sandbox.width(), sandbox.height())
+    );
+    for y in 0..sandbox.height() {
+        for x in 0..sandbox.width() {
+            assert_eq!(loaded.get(x, y), sandbox.get(x, y), "cell ({}, {})", x, y);
+        }
+    }
+}
+
+#[test]
+fn load_fails_on_invalid_magic() {
+    let mut data = save(&sample_sandbox());
+    data[0] = b'X';
+    assert!(matches!(load(&data), Err(SandBoxFileError::InvalidMagic)));
+}
+
+#[test]
+fn load_fails_on_unsupported_version() {
+    let mut data = save(&sample_sandbox());
+    let version = FILE_FORMAT_VERSION + 1;
+    data[VERSION_OFFSET..VERSION_OFFSET + 2].copy_from_slice(&version.to_le_bytes());
+    assert!(matches!(
+        load(&data),
+        Err(SandBoxFileError::UnsupportedVersion(v)) if v == version
+    ));
+}
+
+#[test]
+fn load_fails_on_invalid_size() {
+    let mut data = save(&sample_sandbox());
+    data[WIDTH_OFFSET..WIDTH_OFFSET + 4].copy_from_slice(&100_000u32.to_le_bytes());
+    assert!(matches!(
+        load(&data),
+        Err(SandBoxFileError::InvalidSize {
+            width: 100_000,
+            height: 30
+        })
+    ));
+}
+
+#[test]
+fn load_fails_on_truncated_file() {
+    let data = save(&sample_sandbox());
+    for length in [2, FIRST_RUN_OFFSET - 1, data.len() - 1] {
+        assert!(
+            matches!(load(&data[..length]), Err(SandBoxFileError::Truncated)),
+            "length {}",
+            length
+        );
+    }
+}
+
+#[test]
+fn load_fails_on_unknown_element() {
+    let mut data = save(&sample_sandbox());
+    data[FIRST_RUN_OFFSET + 2] = u8::MAX;
+    assert!(matches!(
+        load(&data),
+        Err(SandBoxFileError::UnknownElement(u8::MAX))
+    ));
+}
+
+#[test]
+fn load_fails_on_invalid_run_length() {
+    let mut data = save(&sample_sandbox());
+    data[FIRST_RUN_OFFSET..FIRST_RUN_OFFSET + 2].copy_from_slice(&0u16.to_le_bytes());
+    assert!(matches!(
+        load(&data),
+        Err(SandBoxFileError::InvalidRunLength)
+    ));
+}
@@ -0,0 +1,263 @@
+// Golden snapshot tests of element behaviour. Each test builds a small scene, simulates it for a
+// fixed number of steps with a fixed seed and compares the elements of all cells with a snapshot
+// in tests/golden. When a snapshot differs, the actual result is written next to it as
+// <name>.actual.txt. After an intended change in behaviour, regenerate the snapshots with:
+//
+//     UPDATE_GOLDEN=1 cargo test --no-default-features --test golden
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use falling_rust::sandbox::*;
+use falling_rust::simulation::{simulation_step, Simulation};
+
+const SEED: u64 = 12345;
+
+// Characters used for the built-in elements in snapshots, indexed by element id
+const ELEMENT_CHARS: &str = ".sRwaDWIrfhol~LepTgux12345#imGMSO=BHP";
+
+#[test]
+fn sand_pile() {
+    check_scene("sand_pile", 32, 32, 150, |sandbox| {
+        fill(sandbox, 14..18, 2..12, Element::Sand);
+    });
+}
+
+#[test]
+fn water_over_drain() {
+    check_scene("water_over_drain", 32, 32, 150, |sandbox| {
+        fill(sandbox, 4..28, 4..10, Element::Water);
+        fill(sandbox, 12..20, 28..29, Element::Drain);
+    });
+}
+
+#[test]
+fn tnt_next_to_wood() {
+    check_scene("tnt_next_to_wood", 48, 32, 150, |sandbox| {
+        fill(sandbox, 4..44, 24..31, Element::Wood);
+        fill(sandbox, 20..28, 18..24, Element::TNT);
+        fill(sandbox, 10..20, 23..24, Element::Fuse);
+        fill(sandbox, 9..10, 23..24, Element::FireSource);
+    });
+}
+
+#[test]
+fn long_fuse() {
+    check_scene("long_fuse", 64, 32, 150, |sandbox| {
+        // A bent fuse of 50 cells, which burns all the way to the TNT at its end
+        fill(sandbox, 3..4, 24..25, Element::FireSource);
+        fill(sandbox, 4..40, 24..25, Element::Fuse);
+        fill(sandbox, 39..40, 10..24, Element::Fuse);
+        fill(sandbox, 40..46, 8..12, Element::TNT);
+    });
+}
+
+#[test]
+fn lava_meets_water() {
+    check_scene("lava_meets_water", 32, 32, 200, |sandbox| {
+        fill(sandbox, 2..12, 20..31, Element::Water);
+        fill(sandbox, 20..30, 2..12, Element::Lava);
+    });
+}
+
+#[test]
+fn oil_floats_on_water() {
+    check_scene("oil_floats_on_water", 32, 32, 200, |sandbox| {
+        fill(sandbox, 4..28, 6..12, Element::Water);
+        fill(sandbox, 4..28, 20..26, Element::Oil);
+    });
+}
+
+#[test]
+fn acid_dissolves_sand() {
+    check_scene("acid_dissolves_sand", 32, 32, 200, |sandbox| {
+        fill(sandbox, 1..31, 24..31, Element::Sand);
+        fill(sandbox, 10..22, 4..10, Element::Acid);
+    });
+}
+
+#[test]
+fn wet_iron_rusts() {
+    check_scene("wet_iron_rusts", 32, 32, 200, |sandbox| {
+        fill(sandbox, 6..26, 20..24, Element::Iron);
+        fill(sandbox, 8..24, 10..20, Element::Water);
+    });
+}
+
+#[test]
+fn seed_grows_into_plant() {
+    check_scene("seed_grows_into_plant", 32, 32, 300, |sandbox| {
+        fill(sandbox, 1..31, 26..31, Element::Sand);
+        fill(sandbox, 1..31, 24..26, Element::Water);
+        fill(sandbox, 15..17, 20..21, Element::Seed);
+    });
+}
+
+#[test]
+fn life_patterns() {
+    check_scene("life_patterns", 32, 32, 40, |sandbox| {
+        // A glider, a blinker and a block
+        let cells = [(5, 4), (6, 5), (4, 6), (5, 6), (6, 6)]
+            .into_iter()
+            .chain([(20, 10), (21, 10), (22, 10)])
+            .chain([(10, 24), (11, 24), (10, 25), (11, 25)]);
+        for (x, y) in cells {
+            sandbox.set_element(x, y, Element::Life);
+        }
+    });
+}
+
+#[test]
+fn battery_powers_heater() {
+    check_scene("battery_powers_heater", 48, 32, 300, |sandbox| {
+        fill(sandbox, 4..5, 20..21, Element::Battery);
+        fill(sandbox, 5..30, 20..21, Element::Wire);
+        fill(sandbox, 30..31, 20..21, Element::Heater);
+        fill(sandbox, 31..36, 16..21, Element::Ice);
+    });
+}
+
+fn fill(
+    sandbox: &mut SandBox,
+    xs: std::ops::Range<usize>,
+    ys: std::ops::Range<usize>,
+    element: Element,
+) {
+    for y in ys {
+        for x in xs.clone() {
+            sandbox.set_element(x, y, element);
+        }
+    }
+}
+
+// Simulate a scene and compare the result with its snapshot
+fn check_scene(
+    name: &str,
+    width: usize,
+    height: usize,
+    steps: u64,
+    build: impl FnOnce(&mut SandBox),
+) {
+    let mut sandbox = SandBox::new(width, height);
+    build(&mut sandbox);
+    let mut simulation = Simulation::with_seed(SEED);
+    for _ in 0..steps {
+        simulation_step(&mut simulation, &mut sandbox);
+    }
+    let actual = snapshot(name, steps, &sandbox);
+
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let path = directory.join(format!("{}.txt", name));
+    let actual_path = directory.join(format!("{}.actual.txt", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(&path, &actual).unwrap();
+        let _ = std::fs::remove_file(&actual_path);
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!(
+            "Missing snapshot {}, create it by running the test with UPDATE_GOLDEN=1",
+            path.display()
+        )
+    });
+    // Snapshots may have been checked out with Windows line endings
+    let expected = expected.replace("\r\n", "\n");
+    if expected == actual {
+        let _ = std::fs::remove_file(&actual_path);
+    } else {
+        std::fs::write(&actual_path, &actual).unwrap();
+        panic!(
+            "Scene {} differs from its snapshot, the actual result is in {}\n{}",
+            name,
+            actual_path.display(),
+            diff(&expected, &actual)
+        );
+    }
+}
+
+// Text snapshot of the elements of all cells, with a legend of the characters used
+fn snapshot(name: &str, steps: u64, sandbox: &SandBox) -> String {
+    let mut present = Vec::new();
+    let mut grid = String::new();
+    for y in 0..sandbox.height() {
+        for x in 0..sandbox.width() {
+            let element = sandbox.get(x, y).element;
+            if !present.contains(&element) {
+                present.push(element);
+            }
+            grid.push(element_char(element));
+        }
+        grid.push('\n');
+    }
+
+    let mut text = format!(
+        "{} after {} steps with seed {}, {}x{} cells\n",
+        name,
+        steps,
+        SEED,
+        sandbox.width(),
+        sandbox.height()
+    );
+    present.sort_by_key(|element| element.id());
+    for element in present {
+        writeln!(text, "{} {}", element_char(element), element).unwrap();
+    }
+    text.push('\n');
+    text.push_str(&grid);
+    text
+}
+
+fn element_char(element: Element) -> char {
+    ELEMENT_CHARS
+        .chars()
+        .nth(element.id() as usize)
+        .unwrap_or('?')
+}
+
+// Readable description of the differences between two snapshots: the legends when they differ,
+// and the rows that differ side by side with the differing cells marked
+fn diff(expected: &str, actual: &str) -> String {
+    let (expected_header, expected_grid) = expected.split_once("\n\n").unwrap_or((expected, ""));
+    let (actual_header, actual_grid) = actual.split_once("\n\n").unwrap_or((actual, ""));
+    let mut text = String::new();
+    if expected_header != actual_header {
+        writeln!(text, "expected header:\n{}", expected_header).unwrap();
+        writeln!(text, "actual header:\n{}\n", actual_header).unwrap();
+    }
+
+    let expected_rows: Vec<&str> = expected_grid.lines().collect();
+    let actual_rows: Vec<&str> = actual_grid.lines().collect();
+    let mut differing_cells = 0;
+    writeln!(text, "row  expected | actual | differences").unwrap();
+    for y in 0..expected_rows.len().max(actual_rows.len()) {
+        let expected_row = expected_rows.get(y).copied().unwrap_or("");
+        let actual_row = actual_rows.get(y).copied().unwrap_or("");
+        let expected_chars: Vec<char> = expected_row.chars().collect();
+        let actual_chars: Vec<char> = actual_row.chars().collect();
+        let marks: String = (0..expected_chars.len().max(actual_chars.len()))
+            .map(|x| {
+                if expected_chars.get(x) == actual_chars.get(x) {
+                    ' '
+                } else {
+                    differing_cells += 1;
+                    '^'
+                }
+            })
+            .collect();
+        if marks.trim().is_empty() {
+            continue;
+        }
+        writeln!(
+            text,
+            "{:3}  {} | {} | {}",
+            y,
+            expected_row,
+            actual_row,
+            marks.trim_end()
+        )
+        .unwrap();
+    }
+    writeln!(text, "{} cells differ", differing_cells).unwrap();
+    text
+}
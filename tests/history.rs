@@ -0,0 +1,89 @@
+// Tests of undo and redo of strokes painted into a running simulation
+
+use falling_rust::history::History;
+use falling_rust::sandbox::*;
+use falling_rust::simulation::{simulation_step, Simulation};
+use falling_rust::toolbox::{Tool, ToolBox};
+
+fn pixel_tool(element: Element) -> ToolBox {
+    ToolBox {
+        tool: Tool::Pixel,
+        element,
+        tool_size: 1,
+        ..Default::default()
+    }
+}
+
+// Painted sand falls away before the stroke ends, the stroke still undoes and redoes the painting
+#[test]
+fn stroke_keeps_painted_state_after_simulation() {
+    let mut sandbox = SandBox::new(32, 32);
+    let mut simulation = Simulation::new();
+    let mut history = History::default();
+    let mut toolbox = pixel_tool(Element::Sand);
+
+    history.begin_stroke();
+    toolbox.apply_tracked(&mut sandbox, 10, 5, history.stroke_mut());
+    for _ in 0..5 {
+        simulation_step(&mut simulation, &mut sandbox);
+    }
+    assert_eq!(sandbox.get(10, 5).element, Element::Air);
+    history.end_stroke();
+    assert!(history.can_undo());
+
+    history.undo(&mut sandbox);
+    assert_eq!(sandbox.get(10, 5).element, Element::Air);
+    history.redo(&mut sandbox);
+    assert_eq!(sandbox.get(10, 5).element, Element::Sand);
+}
+
+// Undoing a stroke puts back the temperature the cells had, not that of their element
+#[test]
+fn undo_restores_temperature() {
+    let mut sandbox = SandBox::new(32, 32);
+    let mut history = History::default();
+    let mut toolbox = pixel_tool(Element::Wood);
+    sandbox.set_element(10, 5, Element::Rock);
+    sandbox.get_mut(10, 5).temperature = 300;
+
+    history.begin_stroke();
+    toolbox.apply_tracked(&mut sandbox, 10, 5, history.stroke_mut());
+    history.end_stroke();
+    assert_eq!(sandbox.get(10, 5).element, Element::Wood);
+
+    history.undo(&mut sandbox);
+    let cell = *sandbox.get(10, 5);
+    assert_eq!(cell.element, Element::Rock);
+    assert_eq!({ cell.temperature }, 300);
+}
+
+// Lowering the memory budget drops the oldest strokes right away, redo steps first
+#[test]
+fn lowering_budget_drops_oldest_strokes() {
+    let mut sandbox = SandBox::new(32, 32);
+    let mut history = History::default();
+    let mut toolbox = pixel_tool(Element::Rock);
+    for x in 1..11 {
+        history.begin_stroke();
+        toolbox.apply_tracked(&mut sandbox, x, 5, history.stroke_mut());
+        history.end_stroke();
+    }
+    history.undo(&mut sandbox);
+    let stroke_size = history.memory_usage() / 10;
+    assert!(stroke_size > 0);
+
+    history.set_memory_budget(stroke_size * 9);
+    assert_eq!(history.memory_usage(), stroke_size * 9);
+    assert!(!history.can_redo());
+    history.set_memory_budget(stroke_size * 4);
+    assert_eq!(history.memory_usage(), stroke_size * 4);
+    for _ in 0..4 {
+        history.undo(&mut sandbox);
+    }
+    assert!(!history.can_undo());
+    assert_eq!(sandbox.get(6, 5).element, Element::Air);
+    assert_eq!(sandbox.get(5, 5).element, Element::Rock);
+
+    history.clear();
+    assert_eq!(history.memory_usage(), 0);
+}
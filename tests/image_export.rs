@@ -0,0 +1,61 @@
+// Tests of exporting sandboxes as images
+
+use image::RgbaImage;
+
+use falling_rust::sandbox::*;
+
+// A sandbox with glowing lava next to other cells
+fn sample_sandbox() -> SandBox {
+    let mut sandbox = SandBox::new(12, 10);
+    for x in 2..10 {
+        sandbox.set_element(x, 7, Element::Rock);
+        sandbox.set_element(x, 8, Element::Sand);
+    }
+    sandbox.set_element(5, 6, Element::Lava);
+    sandbox.set_element(6, 6, Element::Water);
+    sandbox
+}
+
+// Decode exported PNG data, checking it has the size of the sandbox times the scale
+fn decode(data: &[u8], sandbox: &SandBox, scale: u32) -> RgbaImage {
+    let image = image::load_from_memory(data).unwrap().to_rgba8();
+    assert_eq!(
+        image.dimensions(),
+        (
+            sandbox.width() as u32 * scale,
+            sandbox.height() as u32 * scale
+        )
+    );
+    image
+}
+
+// Exported pixels have exactly the colors of the cells, without glow around the lava
+#[test]
+fn exported_pixels_are_cell_colors() {
+    let sandbox = sample_sandbox();
+    let mut data = Vec::new();
+    sandbox.export_png(&mut data, 2).unwrap();
+    let image = decode(&data, &sandbox, 2);
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let (x, y) = (x as usize / 2, y as usize / 2);
+        let (r, g, b) = sandbox.get(x, y).shaded_color(x, y);
+        assert_eq!(pixel.0, [r, g, b, 255], "cell ({}, {})", x, y);
+    }
+}
+
+#[test]
+fn exported_overlay_pixels_are_overlay_colors() {
+    let sandbox = sample_sandbox();
+    for overlay in OVERLAYS {
+        let mut data = Vec::new();
+        write_png(&mut data, &sandbox.to_overlay_image(overlay), 1).unwrap();
+        let image = decode(&data, &sandbox, 1);
+
+        for (x, y, pixel) in image.enumerate_pixels() {
+            let (x, y) = (x as usize, y as usize);
+            let (r, g, b) = sandbox.overlay_color(x, y, overlay);
+            assert_eq!(pixel.0, [r, g, b, 255], "{} at ({}, {})", overlay, x, y);
+        }
+    }
+}
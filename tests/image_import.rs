@@ -0,0 +1,97 @@
+// Tests of importing images as sandboxes
+
+use image::{ImageFormat, Rgba, RgbaImage};
+
+use falling_rust::sandbox::*;
+
+fn pixel((r, g, b): (u8, u8, u8)) -> Rgba<u8> {
+    Rgba([r, g, b, 255])
+}
+
+#[test]
+fn pixels_map_to_nearest_element() {
+    let sand = element_type(Element::Sand).color;
+    let water = element_type(Element::Water).color;
+    let mut image = RgbaImage::from_pixel(6, 5, pixel(element_type(Element::Air).color));
+    image.put_pixel(1, 1, pixel(sand));
+    image.put_pixel(2, 1, pixel(water));
+    // Slightly off colors, e.g. from anti-aliasing, still map to the element
+    image.put_pixel(3, 1, pixel((sand.0 - 3, sand.1 + 2, sand.2)));
+    image.put_pixel(1, 2, pixel((water.0, water.1 + 3, water.2 - 2)));
+    // Transparent pixels are air whatever their color
+    image.put_pixel(2, 2, Rgba([sand.0, sand.1, sand.2, 0]));
+
+    let sandbox = SandBox::from_image(&image, &ElementPalette::elements()).unwrap();
+    let element = |x, y| sandbox.get(x, y).element;
+    assert_eq!(element(1, 1), Element::Sand);
+    assert_eq!(element(2, 1), Element::Water);
+    assert_eq!(element(3, 1), Element::Sand);
+    assert_eq!(element(1, 2), Element::Water);
+    assert_eq!(element(2, 2), Element::Air);
+    assert_eq!(element(4, 3), Element::Air);
+    // The border is indestructible, whatever the image has there
+    assert_eq!(element(0, 0), Element::Indestructible);
+    assert_eq!(element(5, 4), Element::Indestructible);
+}
+
+#[test]
+fn explicit_palette_maps_colors() {
+    let palette = ElementPalette::new(vec![
+        ((255, 0, 0), Element::Lava),
+        ((0, 0, 255), Element::Water),
+    ]);
+    let mut image = RgbaImage::from_pixel(5, 3, pixel((200, 20, 20)));
+    image.put_pixel(2, 1, pixel((30, 30, 220)));
+
+    let sandbox = SandBox::from_image(&image, &palette).unwrap();
+    assert_eq!(sandbox.get(1, 1).element, Element::Lava);
+    assert_eq!(sandbox.get(2, 1).element, Element::Water);
+    assert_eq!(sandbox.get(3, 1).element, Element::Lava);
+}
+
+// Elements that cannot be selected in the interface are never imported, even from their own color
+#[test]
+fn palette_excludes_unselectable_elements() {
+    let palette = ElementPalette::elements();
+    for element in [Element::Indestructible, Element::Explosion] {
+        assert!(!element_type(element).selectable);
+        assert_ne!(palette.nearest(element_type(element).color), element);
+    }
+    assert_eq!(
+        palette.nearest(element_type(Element::Air).color),
+        Element::Air
+    );
+}
+
+#[test]
+fn import_rejects_invalid_sizes() {
+    let palette = ElementPalette::elements();
+    for (width, height) in [(2, 10), (10, 2), (8193, 3), (3, 8193)] {
+        let image = RgbaImage::new(width, height);
+        assert!(
+            matches!(
+                SandBox::from_image(&image, &palette),
+                Err(ImageImportError::InvalidSize { width: w, height: h }) if (w, h) == (width, height)
+            ),
+            "{}x{}",
+            width,
+            height
+        );
+    }
+    assert!(SandBox::from_image(&RgbaImage::new(3, 3), &palette).is_ok());
+}
+
+#[test]
+fn png_import_rejects_oversized_images() {
+    let mut data = Vec::new();
+    RgbaImage::new(10_000, 4)
+        .write_to(&mut std::io::Cursor::new(&mut data), ImageFormat::Png)
+        .unwrap();
+    assert!(matches!(
+        SandBox::import_png(&mut data.as_slice(), &ElementPalette::elements()),
+        Err(ImageImportError::InvalidSize {
+            width: 10_000,
+            height: 4
+        })
+    ));
+}
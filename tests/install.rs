@@ -0,0 +1,21 @@
+// Tests of installing element definitions, in a test binary of their own as installing changes
+// the elements for the whole process
+
+use falling_rust::sandbox::*;
+
+// Definitions are installed completely or not at all
+#[test]
+fn install_fails_without_changes_when_reactions_are_in_use() {
+    let definitions = parse_element_definitions(
+        r#"[(name: "Salt", form: Powder, color: (240, 240, 232), flags: [DissolvesInAcid])]"#,
+    )
+    .unwrap();
+    assert!(!element_types_installed());
+    reactions();
+
+    assert!(matches!(
+        definitions.install(),
+        Err(ElementFileError::AlreadyInstalled)
+    ));
+    assert!(!element_types_installed());
+}
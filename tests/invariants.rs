@@ -0,0 +1,228 @@
+// Property-based tests of invariants the simulation relies on, using random sandboxes and random
+// tool edits in between steps. The number of cases can be raised with the PROPTEST_CASES
+// environment variable for longer runs.
+
+use proptest::prelude::*;
+
+use falling_rust::sandbox::*;
+use falling_rust::simulation::{simulation_step, Simulation};
+use falling_rust::toolbox::{Tool, ToolBox};
+
+// Elements that only move around when left alone at room temperature
+const INERT_ELEMENTS: [Element; 5] = [
+    Element::Air,
+    Element::Sand,
+    Element::Rock,
+    Element::Water,
+    Element::Oil,
+];
+
+// A tool edit made before a simulation step
+#[derive(Clone, Debug)]
+struct Edit {
+    step: usize,
+    tool: Tool,
+    element: Element,
+    size: usize,
+    x: usize,
+    y: usize,
+}
+
+// Random sandbox with its steps to simulate, the seed and edits. Sizes include sandboxes smaller
+// than a chunk and sandboxes with partial chunks at the edges.
+#[derive(Clone, Debug)]
+struct Scenario {
+    width: usize,
+    height: usize,
+    cells: Vec<Element>,
+    seed: u64,
+    steps: usize,
+    edits: Vec<Edit>,
+}
+
+fn all_builtin_elements() -> Vec<Element> {
+    (0..BUILTIN_ELEMENT_COUNT as u8)
+        .map(|id| Element::try_from(id).unwrap())
+        .collect()
+}
+
+fn any_tool() -> impl Strategy<Value = Tool> {
+    prop_oneof![
+        Just(Tool::Pixel),
+        Just(Tool::Circle),
+        Just(Tool::Square),
+        Just(Tool::Spray),
+        Just(Tool::Fill),
+    ]
+}
+
+// Scenario using the given elements for cells and edits
+fn scenario(elements: Vec<Element>, with_edits: bool) -> impl Strategy<Value = Scenario> {
+    (3..80usize, 3..80usize, 1..60usize).prop_flat_map(move |(width, height, steps)| {
+        let edit = (
+            0..steps,
+            any_tool(),
+            prop::sample::select(elements.clone()),
+            1..20usize,
+            0..width,
+            0..height,
+        )
+            .prop_map(|(step, tool, element, size, x, y)| Edit {
+                step,
+                tool,
+                element,
+                size,
+                x,
+                y,
+            });
+        // An empty range is not allowed, 0..1 always generates no edits
+        let edit_count = if with_edits { 0..8usize } else { 0..1usize };
+        (
+            prop::collection::vec(prop::sample::select(elements.clone()), width * height),
+            any::<u64>(),
+            prop::collection::vec(edit, edit_count),
+        )
+            .prop_map(move |(cells, seed, edits)| Scenario {
+                width,
+                height,
+                cells,
+                seed,
+                steps,
+                edits,
+            })
+    })
+}
+
+impl Scenario {
+    fn build(&self) -> SandBox {
+        let mut sandbox = SandBox::new(self.width, self.height);
+        for y in 1..self.height - 1 {
+            for x in 1..self.width - 1 {
+                sandbox.set_element(x, y, self.cells[x + y * self.width]);
+            }
+        }
+        sandbox
+    }
+
+    // Simulate the scenario, checking the sandbox after every step
+    fn run(
+        &self,
+        mut check: impl FnMut(&SandBox) -> Result<(), TestCaseError>,
+    ) -> Result<(), TestCaseError> {
+        let mut sandbox = self.build();
+        let mut simulation = Simulation::with_seed(self.seed);
+        let mut toolbox = ToolBox::with_seed(self.seed);
+        for step in 0..self.steps {
+            for edit in self.edits.iter().filter(|edit| edit.step == step) {
+                toolbox.tool = edit.tool;
+                toolbox.element = edit.element;
+                toolbox.tool_size = edit.size;
+                toolbox.apply(&mut sandbox, edit.x, edit.y);
+            }
+            simulation_step(&mut simulation, &mut sandbox);
+            check(&sandbox)?;
+        }
+        Ok(())
+    }
+}
+
+fn element_counts(sandbox: &SandBox) -> Vec<usize> {
+    let mut counts = vec![0; BUILTIN_ELEMENT_COUNT];
+    for y in 0..sandbox.height() {
+        for x in 0..sandbox.width() {
+            counts[sandbox.get(x, y).element.id() as usize] += 1;
+        }
+    }
+    counts
+}
+
+fn border(width: usize, height: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..width)
+        .flat_map(move |x| [(x, 0), (x, height - 1)])
+        .chain((0..height).flat_map(move |y| [(0, y), (width - 1, y)]))
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    // Updating any mix of elements and edits never accesses cells outside the sandbox, which would
+    // panic, and never changes the indestructible border
+    #[test]
+    fn border_is_never_modified(scenario in scenario(all_builtin_elements(), true)) {
+        scenario.run(|sandbox| {
+            for (x, y) in border(sandbox.width(), sandbox.height()) {
+                prop_assert_eq!(
+                    sandbox.get(x, y).element,
+                    Element::Indestructible,
+                    "border cell ({}, {}) was changed",
+                    x,
+                    y
+                );
+            }
+            Ok(())
+        })?;
+    }
+
+    // Elements without reactions at room temperature only move, so their counts stay the same
+    #[test]
+    fn inert_elements_are_conserved(
+        scenario in scenario(INERT_ELEMENTS.to_vec(), false)
+    ) {
+        let initial = element_counts(&scenario.build());
+        scenario.run(|sandbox| {
+            prop_assert_eq!(element_counts(sandbox), initial.clone());
+            Ok(())
+        })?;
+    }
+
+    // Swapping cells moves both cells without duplicating or losing anything, except for the
+    // border which cannot be swapped
+    #[test]
+    fn swap_moves_cells(
+        scenario in scenario(all_builtin_elements(), false),
+        swaps in prop::collection::vec((0..80usize, 0..80usize, 0..80usize, 0..80usize), 1..50),
+    ) {
+        let mut sandbox = scenario.build();
+        for (x, y, x2, y2) in swaps {
+            let (x, y) = (x % sandbox.width(), y % sandbox.height());
+            let (x2, y2) = (x2 % sandbox.width(), y2 % sandbox.height());
+            let before = element_counts(&sandbox);
+            let (cell, cell2) = (*sandbox.get(x, y), *sandbox.get(x2, y2));
+            sandbox.swap(x, y, x2, y2);
+            prop_assert_eq!(element_counts(&sandbox), before);
+            if cell.element == Element::Indestructible || cell2.element == Element::Indestructible {
+                prop_assert_eq!(sandbox.get(x, y).element, cell.element);
+                prop_assert_eq!(sandbox.get(x2, y2).element, cell2.element);
+            } else {
+                let (moved, moved2) = (sandbox.get(x2, y2), sandbox.get(x, y));
+                prop_assert_eq!(
+                    (moved.element, moved.strength, moved.variant, moved.temperature),
+                    (cell.element, cell.strength, cell.variant, cell.temperature)
+                );
+                prop_assert_eq!(
+                    (moved2.element, moved2.strength, moved2.variant, moved2.temperature),
+                    (cell2.element, cell2.strength, cell2.variant, cell2.temperature)
+                );
+            }
+        }
+    }
+}
+
+// Heat flow between nearly even temperatures is rounded at random, which must not keep a scene
+// awake once only its temperatures still change
+#[test]
+fn settled_scene_falls_asleep() {
+    let mut sandbox = SandBox::new(128, 128);
+    for y in 40..127 {
+        for x in 1..127 {
+            let lava = y < 64 && x % 32 < 10;
+            let element = if lava { Element::Lava } else { Element::Sand };
+            sandbox.set_element(x, y, element);
+        }
+    }
+    let mut simulation = Simulation::new();
+    for _ in 0..4000 {
+        simulation_step(&mut simulation, &mut sandbox);
+    }
+    assert_eq!(sandbox.awake_chunk_count(), 0);
+}
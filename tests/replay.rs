@@ -0,0 +1,129 @@
+// Tests of recording an editing session and playing it back
+
+use falling_rust::history::History;
+use falling_rust::replay::*;
+use falling_rust::sandbox::*;
+use falling_rust::simulation::{simulation_step, Simulation};
+use falling_rust::toolbox::{Tool, ToolBox};
+
+// An editing session with the recorder attached, making changes the way the interface does
+struct Session {
+    sandbox: SandBox,
+    simulation: Simulation,
+    toolbox: ToolBox,
+    history: History,
+    recorder: Recorder,
+}
+
+impl Session {
+    fn record(&mut self, event: ReplayEvent) {
+        self.recorder.record(self.simulation.tick, event);
+    }
+
+    fn apply(&mut self, tool: Tool, element: Element, tool_size: usize, x: usize, y: usize) {
+        self.toolbox.tool = tool;
+        self.toolbox.element = element;
+        self.toolbox.tool_size = tool_size;
+        self.record(ReplayEvent::apply(&self.toolbox, x, y));
+        self.history.begin_stroke();
+        self.toolbox
+            .apply_tracked(&mut self.sandbox, x, y, self.history.stroke_mut());
+        self.history.end_stroke();
+    }
+
+    fn set_running(&mut self, running: bool) {
+        self.simulation.running = running;
+        self.record(ReplayEvent::SetRunning(running));
+    }
+
+    fn step(&mut self) {
+        self.simulation.step = true;
+        self.record(ReplayEvent::Step);
+    }
+
+    fn undo(&mut self) {
+        let cells = self.history.undo(&mut self.sandbox);
+        self.record(ReplayEvent::SetCells(cells));
+    }
+
+    fn replace_sandbox(&mut self, sandbox: SandBox) {
+        self.record(ReplayEvent::sandbox_replaced(&sandbox));
+        self.sandbox = sandbox;
+        self.history.clear();
+    }
+
+    // Frames of the interface, which simulate a step unless paused
+    fn frames(&mut self, count: usize) {
+        for _ in 0..count {
+            simulation_step(&mut self.simulation, &mut self.sandbox);
+        }
+    }
+}
+
+// A recording saved to a file and loaded again plays back to the same final sandbox
+#[test]
+fn replay_reproduces_recorded_session() {
+    let mut sandbox = SandBox::new(64, 48);
+    for x in 10..50 {
+        sandbox.set_element(x, 40, Element::Rock);
+    }
+    let mut session = Session {
+        sandbox,
+        simulation: Simulation::new(),
+        toolbox: ToolBox::default(),
+        history: History::default(),
+        recorder: Recorder::default(),
+    };
+    session.frames(3);
+    session
+        .recorder
+        .start(&mut session.sandbox, &session.simulation, &session.toolbox);
+
+    session.apply(Tool::Circle, Element::Sand, 6, 20, 10);
+    session.frames(10);
+    session.apply(Tool::Spray, Element::Water, 8, 40, 12);
+    session.apply(Tool::Pixel, Element::Fire, 1, 30, 38);
+    session.frames(15);
+    session.set_running(false);
+    session.frames(5);
+    session.step();
+    session.frames(1);
+    session.apply(Tool::Square, Element::Oil, 4, 45, 20);
+    session.step();
+    session.frames(3);
+    session.undo();
+    session.set_running(true);
+    session.frames(20);
+
+    let mut sandbox = SandBox::new(40, 40);
+    for x in 5..35 {
+        sandbox.set_element(x, 30, Element::Wood);
+    }
+    session.replace_sandbox(sandbox);
+    session.frames(5);
+    session.apply(Tool::Circle, Element::Lava, 3, 20, 20);
+    session.frames(30);
+    let recording = session.recorder.stop(&session.simulation).unwrap();
+
+    let mut data = Vec::new();
+    recording.save_to(&mut data).unwrap();
+    let loaded = Recording::load_from(&mut data.as_slice()).unwrap();
+    let (sandbox, simulation) = ReplayPlayer::new(loaded).run().unwrap();
+
+    assert_eq!(simulation.tick, session.simulation.tick);
+    assert_eq!(
+        (sandbox.width(), sandbox.height()),
+        (session.sandbox.width(), session.sandbox.height())
+    );
+    for y in 0..sandbox.height() {
+        for x in 0..sandbox.width() {
+            assert_eq!(
+                sandbox.get(x, y),
+                session.sandbox.get(x, y),
+                "cell ({}, {})",
+                x,
+                y
+            );
+        }
+    }
+}
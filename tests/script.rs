@@ -0,0 +1,78 @@
+// Tests of element scripts, in a test binary of their own as installing element definitions
+// changes the elements for the whole process
+#![cfg(feature = "scripting")]
+
+use falling_rust::sandbox::*;
+use falling_rust::simulation::{simulation_step, Simulation};
+
+fn element(name: &str) -> Element {
+    all_elements()
+        .find(|element| element_type(*element).name == name)
+        .unwrap()
+}
+
+#[test]
+fn scripts_have_a_step_budget_per_chunk_and_report_errors() {
+    parse_element_definitions(
+        r#"[
+            (
+                name: "Counter",
+                form: Solid,
+                color: (200, 200, 200),
+                script: "cells.set_variant(0, 0, cells.variant(0, 0) + 1);",
+                script_step_budget: 1000,
+            ),
+            (
+                name: "Broken",
+                form: Solid,
+                color: (200, 0, 0),
+                script: "cells.set_variant(0, 0, 256);",
+            ),
+        ]"#,
+    )
+    .unwrap()
+    .install()
+    .unwrap();
+    let (counter, broken) = (element("Counter"), element("Broken"));
+
+    let mut sandbox = SandBox::new(128, 128);
+    for y in 1..127 {
+        for x in 1..127 {
+            sandbox.set_element(x, y, counter);
+        }
+    }
+    sandbox.set_element(1, 1, broken);
+    sandbox.set_element(126, 126, broken);
+    let mut simulation = Simulation::new();
+    simulation_step(&mut simulation, &mut sandbox);
+
+    // Every full chunk ran the script for the same number of cells, whatever the order the chunks
+    // were updated in
+    let updated = |chunk_x: usize, chunk_y: usize| {
+        let mut count = 0;
+        for y in chunk_y * CHUNK_SIZE..(chunk_y + 1) * CHUNK_SIZE {
+            for x in chunk_x * CHUNK_SIZE..(chunk_x + 1) * CHUNK_SIZE {
+                count += (sandbox.get(x, y).variant == 1) as usize;
+            }
+        }
+        count
+    };
+    let counts = [updated(1, 1), updated(2, 1), updated(1, 2), updated(2, 2)];
+    assert!(counts[0] > 0 && counts[0] < CHUNK_SIZE * CHUNK_SIZE);
+    assert!(
+        counts.iter().all(|count| *count == counts[0]),
+        "{:?}",
+        counts
+    );
+
+    // A failing script is reported once, and stopped at the end of the step
+    assert_eq!(
+        simulation.script_errors.len(),
+        1,
+        "{:?}",
+        simulation.script_errors
+    );
+    assert!(simulation.script_errors[0].starts_with("Stopped the script of Broken"));
+    simulation_step(&mut simulation, &mut sandbox);
+    assert_eq!(simulation.script_errors.len(), 1);
+}